@@ -1,161 +1,190 @@
-use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
-use tracing::{debug, error, info};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
 
-use crate::config::{AiModel, Config};
-use crate::models::{AnthropicCompletion, Content, GptCompletion, Message, RequestBody};
+use crate::config::Config;
+use crate::models::{Content, Generation, Message, Spend, StreamEvent, Usage};
+use crate::pricing::estimate_cost;
+use crate::providers::{self, Completion, CompletionOptions, ProviderError, Purpose, StreamSink};
 
-pub async fn fetch_title(slug: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-    match config.ai_model {
-        AiModel::Gpt4 | AiModel::Gpt5 => fetch_title_from_gpt(slug, config).await,
-        AiModel::Claude3 | AiModel::Claude4 => fetch_title_from_claude(slug, config).await,
-    }
-}
-
-pub async fn fetch_content(title: &str, config: &Config) -> Result<Content, Box<dyn std::error::Error>> {
-    match config.ai_model {
-        AiModel::Gpt4 | AiModel::Gpt5 => fetch_content_from_gpt(title, config).await,
-        AiModel::Claude3 | AiModel::Claude4 => fetch_content_from_claude(title, config).await,
-    }
+/// A generation that produced no article. Tokens may have been spent on it
+/// all the same, by the title or by attempts that broke off while streaming.
+#[derive(Debug)]
+pub struct FailedGeneration {
+    pub error: ProviderError,
+    pub spend: Spend,
 }
 
-async fn fetch_title_from_claude(slug: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-    info!("Fetching title from Claude for slug: {}", slug);
-    fetch_from_claude(get_title_messages(slug), config).await
-}
-
-async fn fetch_from_claude(messages: Vec<Message>, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-    let anthropy_api_key = config.get_api_key()?;
-    let url = "https://api.anthropic.com/v1/messages";
+/// Writes the article for `slug`: asks for a title, then for the article
+/// itself, and accounts for tokens, latency and cost of all requests.
+/// The title and the article text are reported to `sink` as they arrive.
+/// Notifying `cancel` abandons the generation, with what it cost so far.
+pub async fn generate_article(
+    slug: &str,
+    config: &Config,
+    sink: Arc<StreamSink>,
+    cancel: &Notify,
+) -> Result<Generation, FailedGeneration> {
+    let started = Instant::now();
+    let mut spend = Spend {
+        provider: config.model.provider.as_str().to_string(),
+        model: config.model.model_id.clone(),
+        usage: Usage::default(),
+        latency_ms: 0,
+        cost_usd: Some(0.0),
+    };
 
-    let model = config.ai_model.api_model();
+    let title = match unless_cancelled(fetch_title(slug, config), cancel).await {
+        Ok(completion) => {
+            charge(&mut spend, config, &completion, completion.usage);
+            completion.text
+        }
+        // The article request would only run into the same outage
+        Err(error) if error.is_retryable() || matches!(error, ProviderError::Cancelled) => {
+            spend.latency_ms = started.elapsed().as_millis() as i64;
+            return Err(FailedGeneration { error, spend });
+        }
+        Err(e) => {
+            warn!("Failed to fetch title for slug '{}': {}", slug, e);
+            capitalize_words(&unslugify(slug))
+        }
+    };
+    let title = title.trim_matches('"');
+    debug!("Generated title: {}", title);
+    sink(StreamEvent::Title(title.to_string()));
 
-    let headers = build_anthropic_headers(anthropy_api_key)?;
-    let body = RequestBody {
-        model: model.to_string(),
-        messages: messages.clone(),
-        max_tokens: 2000,
+    let streamed = Arc::new(Mutex::new(Streamed::default()));
+    let tracking_sink = {
+        let streamed = streamed.clone();
+        move |event: StreamEvent| {
+            streamed.lock().unwrap_or_else(|e| e.into_inner()).record(&event);
+            sink(event)
+        }
     };
+    let result = unless_cancelled(fetch_content(title, config, &tracking_sink), cancel).await;
 
-    let client = reqwest::Client::new();
-    let response = client.post(url).headers(headers).json(&body).send().await;
-    let response = match response {
-        Err(err) => Err(err),
-        Ok(response) => response.json::<AnthropicCompletion>().await,
+    // Providers don't report usage of streams that broke off, estimate it
+    let prompt_chars: usize = get_messages(title).iter().map(|m| m.content.len()).sum();
+    let discarded = streamed
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .discarded(result.is_err());
+    let discarded_usage = Usage {
+        prompt_tokens: (discarded.len() * prompt_chars / 4) as i64,
+        completion_tokens: (discarded.iter().sum::<usize>() / 4) as i64,
     };
 
-    match response {
-        Err(e) => {
-            error!("Failed to fetch title from Claude: {:?}", e);
-            Err("Error fetching title from Claude".into())
+    let result = match result {
+        Ok(completion) => {
+            charge(&mut spend, config, &completion, completion.usage + discarded_usage);
+            Ok(completion.text)
         }
-        Ok(response) => {
-            debug!("Successfully received title from Claude");
-            Ok(response.content[0].text.clone())
-        },
+        Err(error) => {
+            let model = spend.model.clone();
+            add_cost(&mut spend, estimate_cost(config, &model, discarded_usage), discarded_usage);
+            Err(error)
+        }
+    };
+    spend.latency_ms = started.elapsed().as_millis() as i64;
+
+    match result {
+        Ok(content) => Ok(Generation {
+            content: Content {
+                title: title.to_string(),
+                content,
+            },
+            spend,
+        }),
+        Err(error) => Err(FailedGeneration { error, spend }),
     }
 }
 
-async fn fetch_title_from_gpt(slug: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-    info!("Fetching title from GPT for slug: {}", slug);
-    fetch_from_gpt(get_title_messages(slug), config).await
+async fn unless_cancelled<T>(request: impl Future<Output = Result<T, ProviderError>>, cancel: &Notify) -> Result<T, ProviderError> {
+    tokio::select! {
+        result = request => result,
+        _ = cancel.notified() => Err(ProviderError::Cancelled),
+    }
 }
 
-async fn fetch_from_gpt(messages: Vec<Message>, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-    let openai_api_key = config.get_api_key()?;
-    let model = config.ai_model.api_model();
-    let url = "https://api.openai.com/v1/chat/completions";
+/// Characters of article text streamed per attempt. After a restart the
+/// attempt before it is thrown away, but its tokens were paid for.
+#[derive(Debug, Default)]
+struct Streamed {
+    current: usize,
+    discarded: Vec<usize>,
+}
 
-    let headers = build_gpt_headers(openai_api_key)?;
-    let body = RequestBody {
-        model: model.to_string(),
-        messages: messages.clone(),
-        max_tokens: 2000,
-    };
+impl Streamed {
+    fn record(&mut self, event: &StreamEvent) {
+        match event {
+            StreamEvent::Delta(text) => self.current += text.len(),
+            StreamEvent::Restart => self.discard(),
+            StreamEvent::Title(_) => {}
+        }
+    }
 
-    let client = reqwest::Client::new();
-    let response = client.post(url).headers(headers).json(&body).send().await;
-    let response = match response {
-        Err(err) => Err(err),
-        Ok(response) => response.json::<GptCompletion>().await,
-    };
+    fn discard(&mut self) {
+        if self.current > 0 {
+            self.discarded.push(std::mem::take(&mut self.current));
+        }
+    }
 
-    match response {
-        Err(e) => {
-            error!("Failed to fetch title from OpenAI: {:?}", e);
-            Err("Error fetching title from OpenAI".into())
+    /// The lengths of the discarded attempts, the last one included if the
+    /// generation failed.
+    fn discarded(&mut self, failed: bool) -> Vec<usize> {
+        if failed {
+            self.discard();
         }
-        Ok(response) => {
-            debug!("Successfully received title from OpenAI");
-            Ok(response.choices[0].message.content.clone())
-        },
+        std::mem::take(&mut self.discarded)
     }
 }
 
-async fn fetch_content_from_claude(title: &str, config: &Config) -> Result<Content, Box<dyn std::error::Error>> {
-    info!("Fetching content from Claude for title: {}", title);
+/// Adds `usage` of a request answered by `completion` to `spend`.
+fn charge(spend: &mut Spend, config: &Config, completion: &Completion, usage: Usage) {
+    spend.provider = completion.provider.clone();
+    spend.model = completion.model.clone();
+    add_cost(spend, estimate_cost(config, &completion.model, usage), usage);
+}
 
-    let messages = get_messages(title);
-    let response = fetch_from_claude(messages, config).await;
+fn add_cost(spend: &mut Spend, cost: Option<f64>, usage: Usage) {
+    spend.usage = spend.usage + usage;
+    // One unknown price makes the total unknown
+    spend.cost_usd = spend.cost_usd.zip(cost).map(|(total, cost)| total + cost);
+}
 
-    match response {
-        Err(e) => {
-            error!("Failed to fetch content from Claude for title '{}': {:?}", title, e);
-            Ok(Content {
-                title: "".to_string(),
-                content: "".to_string(),
-            })
-        }
-        Ok(response) => {
-            debug!("Successfully received content from Claude for title: {}", title);
-            Ok(Content {
-                title: title.to_string(),
-                content: response,
-            })
-        },
-    }
+pub async fn fetch_title(slug: &str, config: &Config) -> Result<Completion, ProviderError> {
+    let provider = providers::from_config(config)?;
+    info!("Fetching title from {} for slug: {}", provider.name(), slug);
+
+    provider
+        .complete(&get_title_messages(slug), &CompletionOptions::for_purpose(Purpose::Title))
+        .await
 }
 
-async fn fetch_content_from_gpt(title: &str, config: &Config) -> Result<Content, Box<dyn std::error::Error>> {
-    info!("Fetching content from GPT for title: {}", title);
+pub async fn fetch_content(title: &str, config: &Config, sink: &StreamSink) -> Result<Completion, ProviderError> {
+    let provider = providers::from_config(config)?;
+    info!("Fetching content from {} for title: {}", provider.name(), title);
 
     let messages = get_messages(title);
-    let response = fetch_from_gpt(messages, config).await;
+    let response = provider.stream(&messages, &CompletionOptions::for_purpose(Purpose::Article), sink).await;
 
     match response {
         Err(e) => {
-            error!("Failed to fetch content from GPT for title '{}': {:?}", title, e);
-            Ok(Content {
-                title: "".to_string(),
-                content: "".to_string(),
-            })
+            error!("Failed to fetch content from {} for title '{}': {}", provider.name(), title, e);
+            Err(e)
+        }
+        Ok(response) if response.text.trim().is_empty() => {
+            Err(ProviderError::MalformedResponse(format!("{} returned an empty article", response.provider)))
         }
         Ok(response) => {
-            debug!("Successfully received content from GPT for title: {}", title);
-            Ok(Content {
-                title: title.to_string(),
-                content: response,
-            })
+            debug!("Successfully received content from {} ({}) for title: {}", response.provider, response.model, title);
+            Ok(response)
         },
     }
 }
 
-fn build_anthropic_headers(api_key: &str) -> Result<HeaderMap, Box<dyn std::error::Error>> {
-    let mut headers = HeaderMap::new();
-    headers.insert("x-api-key", HeaderValue::from_str(&format!("{}", api_key))?);
-    headers.insert("anthropic-version", HeaderValue::from_str("2023-06-01")?);
-    headers.insert("content-type", HeaderValue::from_str("application/json")?);
-    Ok(headers)
-}
-
-fn build_gpt_headers(api_key: &str) -> Result<HeaderMap, Box<dyn std::error::Error>> {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {}", api_key))?,
-    );
-    Ok(headers)
-}
-
 fn get_title_messages(slug: &str) -> Vec<Message> {
     let prompt = get_title_prompt(slug);
     vec![Message {
@@ -216,4 +245,31 @@ pub fn capitalize_words(s: &str) -> String {
     }
 
     result
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarts_discard_the_attempt_before() {
+        let mut streamed = Streamed::default();
+        streamed.record(&StreamEvent::Restart);
+        streamed.record(&StreamEvent::Delta("abcd".to_string()));
+        streamed.record(&StreamEvent::Delta("ef".to_string()));
+        streamed.record(&StreamEvent::Restart);
+        streamed.record(&StreamEvent::Delta("final".to_string()));
+
+        assert_eq!(streamed.discarded(false), vec![6]);
+    }
+
+    #[test]
+    fn failures_discard_the_last_attempt() {
+        let mut streamed = Streamed::default();
+        streamed.record(&StreamEvent::Delta("abcd".to_string()));
+        streamed.record(&StreamEvent::Restart);
+        streamed.record(&StreamEvent::Delta("ef".to_string()));
+
+        assert_eq!(streamed.discarded(true), vec![4, 2]);
+    }
+}
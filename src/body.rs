@@ -0,0 +1,35 @@
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Frame;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+
+/// Body of every response: either complete or streamed from a channel.
+pub type Body = BoxBody<Bytes, Infallible>;
+
+pub fn full(content: impl Into<Bytes>) -> Body {
+    Full::new(content.into()).boxed()
+}
+
+/// Streams whatever is sent to the returned sender. The response ends when
+/// the sender is dropped.
+pub fn channel() -> (mpsc::Sender<Bytes>, Body) {
+    let (sender, receiver) = mpsc::channel(16);
+    (sender, ChannelBody { receiver }.boxed())
+}
+
+struct ChannelBody {
+    receiver: mpsc::Receiver<Bytes>,
+}
+
+impl hyper::body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.receiver.poll_recv(cx).map(|chunk| chunk.map(|data| Ok(Frame::data(data))))
+    }
+}
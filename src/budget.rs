@@ -0,0 +1,67 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+
+use crate::config::Budget;
+use crate::database::{get_usage_since, DbPool};
+
+/// A budget limit that has been reached.
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    /// "daily" or "monthly"
+    pub period: &'static str,
+    pub limit: String,
+    /// Time until the period ends and the budget is available again
+    pub resets_in: Duration,
+}
+
+/// Checks the recorded usage against the configured budget. Generation is
+/// only checked before it starts, so the last article may overshoot a little.
+pub fn check_budget(pool: &DbPool, budget: &Budget) -> Result<Option<BudgetExceeded>, Box<dyn std::error::Error + Send + Sync>> {
+    let periods = [
+        ("daily", "start of day", budget.daily_usd, budget.daily_tokens),
+        ("monthly", "start of month", budget.monthly_usd, budget.monthly_tokens),
+    ];
+
+    for (period, start, max_usd, max_tokens) in periods {
+        if max_usd.is_none() && max_tokens.is_none() {
+            continue;
+        }
+
+        let (tokens, cost) = get_usage_since(pool, start)?;
+
+        // Without a price the spend could be anything, better stop than overspend
+        if let Some(max_usd) = max_usd.filter(|max| cost.is_none_or(|cost| cost >= *max)) {
+            return Ok(Some(BudgetExceeded {
+                period,
+                limit: format!("${:.2}", max_usd),
+                resets_in: resets_in(period),
+            }));
+        }
+
+        if let Some(max_tokens) = max_tokens.filter(|max| tokens >= *max) {
+            return Ok(Some(BudgetExceeded {
+                period,
+                limit: format!("{} tokens", max_tokens),
+                resets_in: resets_in(period),
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Time until the next day or month starts, in UTC like the usage queries.
+fn resets_in(period: &str) -> Duration {
+    let now = Utc::now().naive_utc();
+    let today = now.date();
+    let next = if period == "daily" {
+        today.succ_opt()
+    } else if today.month() == 12 {
+        NaiveDate::from_ymd_opt(today.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(today.year(), today.month() + 1, 1)
+    };
+
+    next.and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|reset| reset - now)
+        .unwrap_or_else(|| Duration::days(1))
+}
@@ -1,11 +1,54 @@
 use std::env;
+use std::time::Duration;
 
+use crate::pricing::has_price;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiProvider {
+    Anthropic,
+    OpenAi,
+    OpenAiCompatible,
+    Mock,
+}
+
+impl AiProvider {
+    pub fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_lowercase().as_str() {
+            "anthropic" | "claude" => Ok(AiProvider::Anthropic),
+            "openai" => Ok(AiProvider::OpenAi),
+            "openai-compatible" | "local" => Ok(AiProvider::OpenAiCompatible),
+            "mock" => Ok(AiProvider::Mock),
+            _ => Err(format!("Invalid AI provider: {}. Must be 'anthropic', 'openai', 'openai-compatible' or 'mock'", s)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AiProvider::Anthropic => "anthropic",
+            AiProvider::OpenAi => "openai",
+            AiProvider::OpenAiCompatible => "openai-compatible",
+            AiProvider::Mock => "mock",
+        }
+    }
+
+    /// The model id used when neither `AI_MODEL_ID` nor an `AI_MODEL` alias provides one.
+    pub fn default_model(&self) -> Option<&'static str> {
+        match self {
+            AiProvider::Mock => Some("mock"),
+            _ => None,
+        }
+    }
+}
+
+/// The shorthand model names accepted in `AI_MODEL`. Each one expands to a
+/// provider and a default model id, which `AI_MODEL_ID` can still override.
 #[derive(Debug, Clone)]
 pub enum AiModel {
     Gpt4,
     Gpt5,
     Claude3,
     Claude4,
+    OpenAiCompatible,
 }
 
 impl AiModel {
@@ -15,7 +58,8 @@ impl AiModel {
             "gpt5" => Ok(AiModel::Gpt5),
             "claude3" => Ok(AiModel::Claude3),
             "claude4" => Ok(AiModel::Claude4),
-            _ => Err(format!("Invalid AI model: {}. Must be 'gpt4', 'gpt5', 'claude3', or 'claude4'", s)),
+            "openai-compatible" | "local" => Ok(AiModel::OpenAiCompatible),
+            _ => Err(format!("Invalid AI model: {}. Must be 'gpt4', 'gpt5', 'claude3', 'claude4' or 'openai-compatible'", s)),
         }
     }
 
@@ -26,82 +70,537 @@ impl AiModel {
             AiModel::Gpt5 => "gpt5",
             AiModel::Claude3 => "claude3",
             AiModel::Claude4 => "claude4",
+            AiModel::OpenAiCompatible => "openai-compatible",
         }
     }
 
-    pub fn api_model(&self) -> &'static str {
+    pub fn provider(&self) -> AiProvider {
         match self {
-            AiModel::Gpt4 => "gpt-4o",
-            AiModel::Gpt5 => "gpt-5",
-            AiModel::Claude3 => "claude-3-7-sonnet-latest",
-            AiModel::Claude4 => "claude-sonnet-4-6",
+            AiModel::Gpt4 | AiModel::Gpt5 => AiProvider::OpenAi,
+            AiModel::Claude3 | AiModel::Claude4 => AiProvider::Anthropic,
+            AiModel::OpenAiCompatible => AiProvider::OpenAiCompatible,
         }
     }
 
-    #[allow(dead_code)]
-    pub fn is_claude(&self) -> bool {
-        matches!(self, AiModel::Claude3 | AiModel::Claude4)
+    /// The default model id sent to the API. OpenAI-compatible servers have
+    /// no sensible default, so their model id must be configured explicitly.
+    pub fn api_model(&self) -> Option<&'static str> {
+        match self {
+            AiModel::Gpt4 => Some("gpt-4o"),
+            AiModel::Gpt5 => Some("gpt-5"),
+            AiModel::Claude3 => Some("claude-3-7-sonnet-latest"),
+            AiModel::Claude4 => Some("claude-sonnet-4-6"),
+            AiModel::OpenAiCompatible => None,
+        }
+    }
+}
+
+/// A concrete provider and the exact model id to request from it.
+#[derive(Debug, Clone)]
+pub struct ModelSpec {
+    pub provider: AiProvider,
+    pub model_id: String,
+}
+
+impl ModelSpec {
+    /// Resolves `AI_PROVIDER` / `AI_MODEL` / `AI_MODEL_ID` into a model spec.
+    /// `AI_PROVIDER` takes precedence; `AI_MODEL` is kept for the shorthand aliases.
+    fn from_env() -> Result<Self, String> {
+        let provider = env::var("AI_PROVIDER").ok().filter(|s| !s.is_empty());
+        let alias = env::var("AI_MODEL").ok().filter(|s| !s.is_empty());
+        let model_id = env::var("AI_MODEL_ID")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        let alias = alias.map(|a| AiModel::from_str(&a)).transpose()?;
+
+        let provider = match (provider, &alias) {
+            (Some(provider), _) => AiProvider::from_str(&provider)?,
+            (None, Some(alias)) => alias.provider(),
+            (None, None) => return Err("AI_PROVIDER or AI_MODEL environment variable must be set".to_string()),
+        };
+
+        let default_model = alias
+            .filter(|alias| alias.provider() == provider)
+            .and_then(|alias| alias.api_model())
+            .or_else(|| provider.default_model());
+
+        let model_id = match (model_id, default_model) {
+            (Some(model_id), _) => model_id,
+            (None, Some(model_id)) => model_id.to_string(),
+            (None, None) => {
+                return Err(format!("AI_MODEL_ID must be set when using the {} provider", provider.as_str()))
+            }
+        };
+
+        Ok(ModelSpec { provider, model_id })
+    }
+
+    /// Parses one entry of `AI_FALLBACK_MODELS`: either an `AI_MODEL` alias
+    /// (`claude4`), `provider:model-id` (`openai-compatible:llama3.1`) or a bare
+    /// provider that has a default model (`mock`).
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let entry = entry.trim();
+
+        if let Some((provider, model_id)) = entry.split_once(':') {
+            let model_id = model_id.trim();
+            if model_id.is_empty() {
+                return Err(format!("Missing model id in '{}'", entry));
+            }
+            return Ok(ModelSpec {
+                provider: AiProvider::from_str(provider.trim())?,
+                model_id: model_id.to_string(),
+            });
+        }
+
+        if let Ok(alias) = AiModel::from_str(entry) {
+            if let Some(model_id) = alias.api_model() {
+                return Ok(ModelSpec {
+                    provider: alias.provider(),
+                    model_id: model_id.to_string(),
+                });
+            }
+        }
+
+        let provider = AiProvider::from_str(entry)
+            .map_err(|_| format!("Invalid model '{}'. Use an AI_MODEL alias or 'provider:model-id'", entry))?;
+        let model_id = provider
+            .default_model()
+            .ok_or_else(|| format!("'{}' needs a model id, e.g. '{}:model-id'", entry, entry))?;
+        Ok(ModelSpec {
+            provider,
+            model_id: model_id.to_string(),
+        })
+    }
+}
+
+/// Token prices in USD per million tokens, used to estimate generation cost.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelPrice {
+    /// Parses `AI_PRICING`, e.g. `gpt-5=1.25/10,llama3.1=0/0`: model id prefix,
+    /// input price and output price per million tokens.
+    fn parse_list(list: &str) -> Result<Vec<(String, ModelPrice)>, String> {
+        list.split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let invalid = || format!("Invalid AI_PRICING entry '{}'. Expected 'model=input/output'", entry.trim());
+                let (model, prices) = entry.split_once('=').ok_or_else(invalid)?;
+                let (input, output) = prices.split_once('/').ok_or_else(invalid)?;
+                let price = ModelPrice {
+                    input_per_mtok: input.trim().parse().map_err(|_| invalid())?,
+                    output_per_mtok: output.trim().parse().map_err(|_| invalid())?,
+                };
+                Ok((model.trim().to_string(), price))
+            })
+            .collect()
+    }
+}
+
+/// How often and how patiently failed provider requests are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub request_timeout: Duration,
+}
+
+impl RetryPolicy {
+    fn from_env() -> Result<Self, String> {
+        Ok(RetryPolicy {
+            max_retries: parse_env("AI_MAX_RETRIES", 3)?,
+            base_delay: Duration::from_millis(parse_env("AI_RETRY_BASE_DELAY_MS", 1000)?),
+            max_delay: Duration::from_millis(parse_env("AI_RETRY_MAX_DELAY_MS", 30_000)?),
+            request_timeout: Duration::from_secs(parse_env("AI_REQUEST_TIMEOUT_SECS", 120)?),
+        })
+    }
+}
+
+/// Spending limits computed from the recorded generations. Daily limits reset
+/// at midnight UTC, monthly limits on the first of the month.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    pub daily_usd: Option<f64>,
+    pub monthly_usd: Option<f64>,
+    pub daily_tokens: Option<i64>,
+    pub monthly_tokens: Option<i64>,
+}
+
+impl Budget {
+    /// Whether one of the limits is in USD, which needs the price of every model.
+    pub fn limits_cost(&self) -> bool {
+        self.daily_usd.is_some() || self.monthly_usd.is_some()
+    }
+
+    fn from_env() -> Result<Self, String> {
+        Ok(Budget {
+            daily_usd: parse_optional_env("BUDGET_DAILY_USD")?,
+            monthly_usd: parse_optional_env("BUDGET_MONTHLY_USD")?,
+            daily_tokens: parse_optional_env("BUDGET_DAILY_TOKENS")?,
+            monthly_tokens: parse_optional_env("BUDGET_MONTHLY_TOKENS")?,
+        })
+    }
+}
+
+/// How many articles may be generated per window. With a burst set, at most
+/// `burst` of them may be generated within `window / max_articles`, which
+/// spreads generation over the window instead of using it all at once.
+#[derive(Debug, Clone)]
+pub struct RateLimitPolicy {
+    pub max_articles: u32,
+    pub window: Duration,
+    pub window_name: &'static str,
+    pub burst: Option<u32>,
+}
+
+impl RateLimitPolicy {
+    fn from_env() -> Result<Self, String> {
+        let max_articles: u32 = parse_env("RATE_LIMIT_ARTICLES", 1)?;
+        if max_articles == 0 {
+            return Err("RATE_LIMIT_ARTICLES must be at least 1".to_string());
+        }
+
+        let window_name = env::var("RATE_LIMIT_WINDOW").unwrap_or_else(|_| "day".to_string());
+        let (window_name, hours) = match window_name.to_lowercase().as_str() {
+            "hour" => ("hour", 1),
+            "day" => ("day", 24),
+            "week" => ("week", 24 * 7),
+            _ => return Err(format!("Invalid RATE_LIMIT_WINDOW: {}. Must be 'hour', 'day' or 'week'", window_name)),
+        };
+
+        let burst: Option<u32> = parse_optional_env("RATE_LIMIT_BURST")?;
+        if burst == Some(0) {
+            return Err("RATE_LIMIT_BURST must be at least 1".to_string());
+        }
+
+        Ok(RateLimitPolicy {
+            max_articles,
+            window: Duration::from_secs(hours * 3600),
+            window_name,
+            burst,
+        })
+    }
+
+    /// The `(limit, window)` pairs that all have to hold for a new article.
+    pub fn limits(&self) -> Vec<(u32, Duration)> {
+        let mut limits = vec![(self.max_articles, self.window)];
+        if let Some(burst) = self.burst.filter(|burst| *burst < self.max_articles) {
+            limits.push((burst, self.window / self.max_articles));
+        }
+        limits
     }
 }
 
+/// Throttling of generation attempts per client, on top of the global
+/// rate limit. Clients are identified by IP address, optionally combined
+/// with their User-Agent.
+#[derive(Debug, Clone)]
+pub struct ClientRateLimit {
+    /// 0 disables per-client throttling
+    pub max_attempts: u32,
+    pub window: Duration,
+    pub by_user_agent: bool,
+    /// Header carrying the client IP when running behind a reverse proxy,
+    /// e.g. `X-Forwarded-For`. Without it the peer address is used.
+    pub ip_header: Option<String>,
+    /// Number of proxies in front of the server that append to `ip_header`.
+    /// Entries left of theirs come from the client and can't be trusted
+    pub trusted_proxies: usize,
+}
+
+impl ClientRateLimit {
+    fn from_env() -> Result<Self, String> {
+        Ok(ClientRateLimit {
+            max_attempts: parse_env("CLIENT_RATE_LIMIT_ATTEMPTS", 3)?,
+            window: Duration::from_secs(parse_env("CLIENT_RATE_LIMIT_WINDOW_SECS", 3600)?),
+            by_user_agent: parse_bool_env("CLIENT_RATE_LIMIT_BY_USER_AGENT", false)?,
+            ip_header: env::var("CLIENT_IP_HEADER").ok().filter(|s| !s.is_empty()),
+            trusted_proxies: parse_env("CLIENT_IP_TRUSTED_PROXIES", 1)?.max(1),
+        })
+    }
+}
+
+/// How the site presents itself in pages, feeds and meta tags, so several
+/// instances can run under different names.
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub name: String,
+    pub description: String,
+    pub author: Option<String>,
+    /// Contact email address, shown in the footer and the feeds
+    pub contact: Option<String>,
+    /// Markdown (inline HTML allowed) shown at the bottom of every page
+    pub footer: String,
+    /// Language of the site as BCP 47 tag, e.g. `en` or `de-CH`
+    pub language: String,
+}
+
+impl Site {
+    fn from_env() -> Result<Self, String> {
+        let var = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+
+        let footer = match var("SITE_FOOTER_FILE") {
+            Some(path) => std::fs::read_to_string(&path)
+                .map_err(|e| format!("SITE_FOOTER_FILE '{}' could not be read: {}", path, e))?,
+            None => var("SITE_FOOTER").unwrap_or_else(|| DEFAULT_FOOTER.to_string()),
+        };
+
+        Ok(Site {
+            name: var("SITE_NAME").unwrap_or_else(|| "Autoblogger".to_string()),
+            description: var("SITE_DESCRIPTION").unwrap_or_else(|| "Articles automatically generated by a LLM".to_string()),
+            author: var("SITE_AUTHOR"),
+            contact: var("SITE_CONTACT"),
+            footer,
+            language: var("SITE_LANGUAGE").unwrap_or_else(|| "en".to_string()),
+        })
+    }
+}
+
+const DEFAULT_FOOTER: &str = "All articles are automatically generated by a LLM (Large Language Model like GPT-4, GPT-5 \
+or Claude). It can contain hallucinations, nonsense, factual errors, and other inaccuracies. Do not take it seriously \
+in any way. [Source on Github](https://github.com/syeo66/autoblogger)";
+
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub ai_model: AiModel,
+    pub model: ModelSpec,
+    /// Tried in order when the primary model fails
+    pub fallback_models: Vec<ModelSpec>,
     pub openai_api_key: Option<String>,
     pub anthropic_api_key: Option<String>,
+    pub ai_base_url: Option<String>,
+    pub mock_seed: u64,
+    pub retry: RetryPolicy,
+    pub pricing: Vec<(String, ModelPrice)>,
+    /// Enables the admin pages when set
+    pub admin_token: Option<String>,
+    pub site: Site,
+    /// Public URL of the site without trailing slash, for absolute links in
+    /// feeds and canonical links. Derived from the Host header when unset
+    pub base_url: Option<String>,
+    /// Lets search engines crawl the articles and advertises the sitemap
+    pub indexing_enabled: bool,
+    /// Whether generated articles may be indexed before anyone reviewed them
+    pub index_new_articles: bool,
+    /// Sends the robots directives of pages as X-Robots-Tag header, too
+    pub x_robots_tag: bool,
+    /// Served as robots.txt instead of the built-in rules. `{base_url}` and
+    /// `{sitemap_url}` are replaced
+    pub robots_txt: Option<String>,
+    /// Templates in this directory replace the built-in ones
+    pub theme_dir: Option<std::path::PathBuf>,
+    pub budget: Budget,
+    pub rate_limit: RateLimitPolicy,
+    pub client_rate_limit: ClientRateLimit,
+    /// How long a generation lock outlives its last refresh, i.e. how soon
+    /// the generation of a crashed process can be taken over
+    pub generation_lock_ttl: Duration,
     pub db_path: String,
     pub server_port: u16,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
-        let ai_model_str = env::var("AI_MODEL")
-            .map_err(|_| "AI_MODEL environment variable must be set")?;
-        
-        let ai_model = AiModel::from_str(&ai_model_str)?;
+        let model = ModelSpec::from_env()?;
+        let fallback_models = env::var("AI_FALLBACK_MODELS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(ModelSpec::parse)
+            .collect::<Result<Vec<_>, _>>()?;
 
         let openai_api_key = env::var("OPENAI_API_KEY").ok();
         let anthropic_api_key = env::var("ANTHROPIC_API_KEY").ok();
+        let ai_base_url = env::var("AI_BASE_URL").ok().filter(|s| !s.is_empty());
+        let mock_seed = parse_env("MOCK_SEED", 0)?;
+        let retry = RetryPolicy::from_env()?;
+        let pricing = ModelPrice::parse_list(&env::var("AI_PRICING").unwrap_or_default())?;
+        let admin_token = env::var("ADMIN_TOKEN").ok().filter(|s| !s.is_empty());
+        let site = Site::from_env()?;
+        let base_url = env::var("BASE_URL")
+            .ok()
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+        let theme_dir = env::var("THEME_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(|dir| std::path::PathBuf::from(dir.trim()));
+        let indexing_enabled = parse_bool_env("INDEXING_ENABLED", false)?;
+        let index_new_articles = parse_bool_env("INDEX_NEW_ARTICLES", false)?;
+        let x_robots_tag = parse_bool_env("X_ROBOTS_TAG", false)?;
+        let robots_txt = match env::var("ROBOTS_TXT_FILE").ok().filter(|path| !path.trim().is_empty()) {
+            Some(path) => Some(
+                std::fs::read_to_string(path.trim())
+                    .map_err(|e| format!("ROBOTS_TXT_FILE '{}' could not be read: {}", path, e))?,
+            ),
+            None => None,
+        };
+        let budget = Budget::from_env()?;
+        let rate_limit = RateLimitPolicy::from_env()?;
+        let client_rate_limit = ClientRateLimit::from_env()?;
+        let generation_lock_ttl = Duration::from_secs(parse_env("GENERATION_LOCK_TTL_SECS", 60)?.max(1));
 
-        // Validate that the required API key is present for the selected model
-        match ai_model {
-            AiModel::Gpt4 | AiModel::Gpt5 => {
-                if openai_api_key.is_none() {
-                    return Err("OPENAI_API_KEY must be set when using gpt4 or gpt5 model".into());
-                }
-            }
-            AiModel::Claude3 | AiModel::Claude4 => {
-                if anthropic_api_key.is_none() {
-                    return Err("ANTHROPIC_API_KEY must be set when using claude3 or claude4 model".into());
-                }
-            }
-        }
-
-        let db_path = env::var("DB_PATH").unwrap_or_else(|_| "./blog.db".to_string());
+        let db_path = Self::db_path_from_env();
         let server_port = env::var("SERVER_PORT")
             .unwrap_or_else(|_| "3000".to_string())
             .parse::<u16>()
             .map_err(|_| "SERVER_PORT must be a valid port number")?;
 
-        Ok(Config {
-            ai_model,
+        let config = Config {
+            model,
+            fallback_models,
             openai_api_key,
             anthropic_api_key,
+            ai_base_url,
+            mock_seed,
+            retry,
+            pricing,
+            admin_token,
+            site,
+            base_url,
+            indexing_enabled,
+            index_new_articles,
+            x_robots_tag,
+            robots_txt,
+            theme_dir,
+            budget,
+            rate_limit,
+            client_rate_limit,
+            generation_lock_ttl,
             db_path,
             server_port,
-        })
+        };
+
+        for spec in config.model_chain() {
+            config.validate_model(spec)?;
+
+            if config.budget.limits_cost() && !has_price(&config, &spec.model_id) {
+                return Err(format!(
+                    "No price known for model '{}'. Set it with AI_PRICING to use BUDGET_DAILY_USD or BUDGET_MONTHLY_USD",
+                    spec.model_id
+                )
+                .into());
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The database location alone, for commands that need no other settings.
+    pub fn db_path_from_env() -> String {
+        env::var("DB_PATH").unwrap_or_else(|_| "./blog.db".to_string())
     }
 
-    pub fn get_api_key(&self) -> Result<&str, &'static str> {
-        match self.ai_model {
-            AiModel::Gpt4 | AiModel::Gpt5 => {
+    /// The primary model followed by the fallbacks, in the order they are tried.
+    pub fn model_chain(&self) -> impl Iterator<Item = &ModelSpec> {
+        std::iter::once(&self.model).chain(self.fallback_models.iter())
+    }
+
+    /// Validates that the required API key or endpoint is present for `spec`.
+    fn validate_model(&self, spec: &ModelSpec) -> Result<(), String> {
+        match spec.provider {
+            AiProvider::OpenAi => {
+                if self.openai_api_key.is_none() {
+                    return Err("OPENAI_API_KEY must be set when using the openai provider".into());
+                }
+            }
+            AiProvider::Anthropic => {
+                if self.anthropic_api_key.is_none() {
+                    return Err("ANTHROPIC_API_KEY must be set when using the anthropic provider".into());
+                }
+            }
+            AiProvider::OpenAiCompatible => {
+                if self.ai_base_url.is_none() {
+                    return Err("AI_BASE_URL must be set when using the openai-compatible provider".into());
+                }
+            }
+            AiProvider::Mock => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_api_key(&self, provider: AiProvider) -> Result<Option<&str>, &'static str> {
+        let key = match provider {
+            AiProvider::OpenAi => {
                 self.openai_api_key.as_deref()
                     .ok_or("OpenAI API key not configured")
             }
-            AiModel::Claude3 | AiModel::Claude4 => {
+            AiProvider::Anthropic => {
                 self.anthropic_api_key.as_deref()
                     .ok_or("Anthropic API key not configured")
             }
+            // Self-hosted servers usually don't check the key at all
+            AiProvider::OpenAiCompatible => return Ok(self.openai_api_key.as_deref()),
+            AiProvider::Mock => return Ok(None),
+        };
+        key.map(Some)
+    }
+}
+
+/// Reads a numeric environment variable, falling back to `default` when it is unset.
+fn parse_env<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse::<T>()
+            .map_err(|_| format!("{} must be a non-negative integer", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Reads an optional numeric environment variable; unset or empty means `None`.
+fn parse_optional_env<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|_| format!("{} must be a valid number", name)),
+        _ => Ok(None),
+    }
+}
+
+fn parse_bool_env(name: &str, default: bool) -> Result<bool, String> {
+    match env::var(name) {
+        Ok(value) => match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            _ => Err(format!("{} must be 'true' or 'false'", name)),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_articles: u32, burst: Option<u32>) -> RateLimitPolicy {
+        RateLimitPolicy {
+            max_articles,
+            window: Duration::from_secs(24 * 3600),
+            window_name: "day",
+            burst,
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn limits_without_burst() {
+        let day = Duration::from_secs(24 * 3600);
+        assert_eq!(policy(3, None).limits(), vec![(3, day)]);
+        // A burst that allows the whole window at once limits nothing
+        assert_eq!(policy(3, Some(3)).limits(), vec![(3, day)]);
+        assert_eq!(policy(3, Some(5)).limits(), vec![(3, day)]);
+    }
+
+    #[test]
+    fn burst_spreads_articles_over_the_window() {
+        let day = Duration::from_secs(24 * 3600);
+        assert_eq!(policy(4, Some(1)).limits(), vec![(4, day), (1, Duration::from_secs(6 * 3600))]);
+        assert_eq!(policy(24, Some(2)).limits(), vec![(24, day), (2, Duration::from_secs(3600))]);
+    }
+}
@@ -1,11 +1,15 @@
-use chrono::{DateTime, Duration, Local, NaiveDateTime};
+use chrono::{Duration, NaiveDateTime, Utc};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, Connection};
+use rusqlite::params;
 use std::sync::OnceLock;
+use tracing::info;
 
 use crate::config::Config;
-use crate::models::Content;
+use crate::migrations;
+use crate::models::{
+    Article, ArticleSummary, GenerationRecord, Job, JobStatus, MonthlyUsage, SearchResult, Spend, MATCH_END, MATCH_START,
+};
 
 pub type DbPool = Pool<SqliteConnectionManager>;
 
@@ -18,9 +22,12 @@ pub fn init_pool_with_config(config: &Config) -> Result<DbPool, Box<dyn std::err
         .max_size(10)
         .build(manager)?;
     
-    // Initialize database schema
-    let conn = pool.get()?;
-    initialize_database(&conn)?;
+    // Bring the schema up to date
+    let mut conn = pool.get()?;
+    let applied = migrations::run(&mut conn)?;
+    if applied > 0 {
+        info!("Applied {} database migration(s)", applied);
+    }
     
     // Store the pool in the static variable
     DB_POOL.set(pool.clone())
@@ -33,48 +40,63 @@ pub fn get_pool() -> &'static DbPool {
     DB_POOL.get().expect("Database pool not initialized")
 }
 
-
-pub fn initialize_database(conn: &Connection) -> Result<(), rusqlite::Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS articles (
-            slug     TEXT PRIMARY KEY,
-            title    TEXT NOT NULL,
-            content  TEXT NOT NULL,
-            createdAt DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        (),
+pub fn get_article_by_slug(pool: &DbPool, slug: &str) -> Result<Article, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT slug, title, content, createdAt, indexable FROM articles WHERE slug = ?1 LIMIT 1",
     )?;
+    let result = stmt.query_row(params![slug], article_from_row)?;
+    Ok(result)
+}
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS locks (
-            title     TEXT NOT NULL,
-            createdAt DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        (),
+/// One page of articles, newest first: up to `limit` articles published
+/// before the article `before`, or the newest ones without it.
+pub fn get_articles_page(pool: &DbPool, before: Option<&str>, limit: u32) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    // Keyset pagination, the rowid orders articles created within the same second
+    let mut stmt = conn.prepare(
+        "SELECT title, slug FROM articles
+         WHERE ?1 IS NULL OR (createdAt, rowid) < (SELECT createdAt, rowid FROM articles WHERE slug = ?1)
+         ORDER BY createdAt DESC, rowid DESC LIMIT ?2",
     )?;
-
-    Ok(())
+    let rows = stmt.query_map(params![before, limit], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    
+    let mut articles = Vec::new();
+    for row in rows {
+        articles.push(row?);
+    }
+    Ok(articles)
 }
 
-pub fn get_article_by_slug(pool: &DbPool, slug: &str) -> Result<Content, Box<dyn std::error::Error + Send + Sync>> {
+/// Title, slug and creation date of every article, newest first.
+pub fn get_article_index(pool: &DbPool) -> Result<Vec<ArticleSummary>, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let mut stmt = conn.prepare("SELECT title, content FROM articles WHERE slug = ?1 LIMIT 1")?;
-    let result = stmt.query_row(params![slug], |row| {
-        Ok(Content {
+    let mut stmt = conn.prepare("SELECT title, slug, createdAt FROM articles ORDER BY createdAt DESC, rowid DESC")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(ArticleSummary {
             title: row.get(0)?,
-            content: row.get(1)?,
+            slug: row.get(1)?,
+            created_at: row.get(2)?,
         })
     })?;
-    Ok(result)
+
+    let mut articles = Vec::new();
+    for row in rows {
+        articles.push(row?);
+    }
+    Ok(articles)
 }
 
-pub fn get_recent_articles(pool: &DbPool) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+/// The `limit` newest articles with their content.
+pub fn get_latest_articles(pool: &DbPool, limit: u32) -> Result<Vec<Article>, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let mut stmt = conn.prepare("SELECT title, slug FROM articles ORDER BY createdAt DESC LIMIT 20")?;
-    let rows = stmt.query_map([], |row| {
-        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
-    })?;
-    
+    let mut stmt = conn.prepare(
+        "SELECT slug, title, content, createdAt, indexable FROM articles ORDER BY createdAt DESC, rowid DESC LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![limit], article_from_row)?;
+
     let mut articles = Vec::new();
     for row in rows {
         articles.push(row?);
@@ -82,12 +104,110 @@ pub fn get_recent_articles(pool: &DbPool) -> Result<Vec<(String, String)>, Box<d
     Ok(articles)
 }
 
-pub fn check_daily_rate_limit(pool: &DbPool) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+/// Slug and last modification date of `limit` indexable articles from
+/// `offset` on, oldest first so earlier pages stay stable as articles are added.
+pub fn get_sitemap_entries(pool: &DbPool, offset: u32, limit: u32) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT slug, COALESCE(updatedAt, createdAt) FROM articles WHERE indexable = 1
+         ORDER BY createdAt, rowid LIMIT ?1 OFFSET ?2",
+    )?;
+    let rows = stmt.query_map(params![limit, offset], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut entries = Vec::new();
+    for row in rows {
+        entries.push(row?);
+    }
+    Ok(entries)
+}
+
+/// Whether the database answers at all.
+pub fn check_connection(pool: &DbPool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.query_row("SELECT 1", [], |row| row.get::<usize, i64>(0))?;
+    Ok(())
+}
+
+pub fn count_indexable_articles(pool: &DbPool) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let count = conn.query_row("SELECT COUNT(*) FROM articles WHERE indexable = 1", [], |row| row.get(0))?;
+    Ok(count)
+}
+
+/// Number of articles and the creation date of the newest one, which
+/// together change whenever the feeds do.
+pub fn get_articles_version(pool: &DbPool) -> Result<(i64, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
+    let version = conn.query_row("SELECT COUNT(*), MAX(createdAt) FROM articles", [], |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    Ok(version)
+}
+
+/// Full-text search over titles and contents, best matches first. Every word
+/// of `query` has to appear, as a word or the start of one.
+pub fn search_articles(pool: &DbPool, query: &str, limit: u32) -> Result<Vec<SearchResult>, Box<dyn std::error::Error + Send + Sync>> {
+    // Quote every word so user input can't break the FTS5 query syntax
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "")))
+        .filter(|term| term != "\"\"*")
+        .collect();
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT articles.slug,
+                highlight(articles_fts, 0, ?2, ?3),
+                snippet(articles_fts, 1, ?2, ?3, '…', 24)
+         FROM articles_fts JOIN articles ON articles.rowid = articles_fts.rowid
+         WHERE articles_fts MATCH ?1
+         ORDER BY bm25(articles_fts, 10.0, 1.0)
+         LIMIT ?4",
+    )?;
+    let rows = stmt.query_map(
+        params![terms.join(" "), MATCH_START.to_string(), MATCH_END.to_string(), limit],
+        |row| {
+            Ok(SearchResult {
+                slug: row.get(0)?,
+                title: row.get(1)?,
+                snippet: row.get(2)?,
+            })
+        },
+    )?;
+
+    let mut results = Vec::new();
+    for row in rows {
+        results.push(row?);
+    }
+    Ok(results)
+}
+
+/// Returns the creation date of the article that keeps the window full, i.e.
+/// the `limit`-th most recent one within the last `window`, if there is one.
+/// Articles still being generated count as created when their job started.
+pub fn check_rate_limit(pool: &DbPool, limit: u32, window: std::time::Duration) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let since = format!("-{} seconds", window.as_secs());
     let result = conn
-        .prepare("SELECT createdAt FROM articles WHERE createdAt > datetime('now','-1 day') LIMIT 1")?
-        .query_row([], |row| row.get::<usize, String>(0));
-    
+        .prepare(
+            "SELECT createdAt FROM (
+                SELECT createdAt FROM articles
+                UNION ALL
+                SELECT createdAt FROM jobs WHERE status IN (?3, ?4)
+             )
+             WHERE createdAt > datetime('now', ?1)
+             ORDER BY createdAt DESC LIMIT 1 OFFSET ?2",
+        )?
+        .query_row(
+            params![since, limit.saturating_sub(1), JobStatus::Pending.as_str(), JobStatus::Running.as_str()],
+            |row| row.get::<usize, String>(0),
+        );
+
     match result {
         Ok(date_str) => Ok(Some(date_str)),
         Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
@@ -95,40 +215,266 @@ pub fn check_daily_rate_limit(pool: &DbPool) -> Result<Option<String>, Box<dyn s
     }
 }
 
-pub fn check_generation_lock(pool: &DbPool) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+/// Takes the generation lock of `slug` for `owner` until `ttl` from now.
+/// Expired locks are cleaned up first. Returns `false` if someone else holds it.
+pub fn acquire_generation_lock(pool: &DbPool, slug: &str, owner: &str, ttl: std::time::Duration) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    let result = conn
-        .prepare("SELECT createdAt FROM locks WHERE createdAt > datetime('now','-5 minutes') LIMIT 1")?
-        .query_row([], |row| row.get::<usize, String>(0));
-    
+    conn.execute("DELETE FROM generation_locks WHERE expiresAt <= datetime('now')", ())?;
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO generation_locks (slug, owner, expiresAt) VALUES (?1, ?2, datetime('now', ?3))",
+        params![slug, owner, format!("+{} seconds", ttl.as_secs())],
+    )?;
+    Ok(inserted > 0)
+}
+
+/// Extends a lock held by `owner` to `ttl` from now. Returns `false` if the
+/// lock was lost, e.g. because it expired and was taken over.
+pub fn refresh_generation_lock(pool: &DbPool, slug: &str, owner: &str, ttl: std::time::Duration) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let updated = conn.execute(
+        "UPDATE generation_locks SET expiresAt = datetime('now', ?3) WHERE slug = ?1 AND owner = ?2",
+        params![slug, owner, format!("+{} seconds", ttl.as_secs())],
+    )?;
+    Ok(updated > 0)
+}
+
+pub fn release_generation_lock(pool: &DbPool, slug: &str, owner: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "DELETE FROM generation_locks WHERE slug = ?1 AND owner = ?2",
+        params![slug, owner],
+    )?;
+    Ok(())
+}
+
+pub fn is_generation_locked(pool: &DbPool, slug: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let locked = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM generation_locks WHERE slug = ?1 AND expiresAt > datetime('now'))",
+        params![slug],
+        |row| row.get::<usize, bool>(0),
+    )?;
+    Ok(locked)
+}
+
+pub fn insert_article(pool: &DbPool, slug: &str, title: &str, content: &str, indexable: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT INTO articles (slug, title, content, indexable) VALUES (?1, ?2, ?3, ?4)",
+        params![slug, title, content, indexable],
+    )?;
+    Ok(())
+}
+
+/// Allows or forbids search engines to index an article. Returns `false` if
+/// there is no such article.
+pub fn set_article_indexable(pool: &DbPool, slug: &str, indexable: bool) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let updated = conn.execute(
+        "UPDATE articles SET indexable = ?2, updatedAt = CURRENT_TIMESTAMP WHERE slug = ?1",
+        params![slug, indexable],
+    )?;
+    Ok(updated > 0)
+}
+
+fn article_from_row(row: &rusqlite::Row) -> rusqlite::Result<Article> {
+    Ok(Article {
+        slug: row.get(0)?,
+        title: row.get(1)?,
+        content: row.get(2)?,
+        created_at: row.get(3)?,
+        indexable: row.get(4)?,
+    })
+}
+
+/// Records what a generation of `slug` spent. Every attempt gets its own
+/// row, failed ones and repeated generations of a slug included.
+pub fn insert_generation(pool: &DbPool, slug: &str, spend: &Spend, succeeded: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT INTO generations (slug, provider, model, promptTokens, completionTokens, latencyMs, costUsd, succeeded)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            slug,
+            spend.provider,
+            spend.model,
+            spend.usage.prompt_tokens,
+            spend.usage.completion_tokens,
+            spend.latency_ms,
+            spend.cost_usd,
+            succeeded,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn get_recent_generations(pool: &DbPool, limit: i64) -> Result<Vec<GenerationRecord>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT generations.slug, provider, model, promptTokens, completionTokens, latencyMs, costUsd,
+                generations.createdAt, articles.indexable, succeeded
+         FROM generations LEFT JOIN articles ON articles.slug = generations.slug
+         ORDER BY generations.createdAt DESC, generations.id DESC LIMIT ?1",
+    )?;
+    let rows = stmt.query_map(params![limit], |row| {
+        Ok(GenerationRecord {
+            slug: row.get(0)?,
+            provider: row.get(1)?,
+            model: row.get(2)?,
+            prompt_tokens: row.get(3)?,
+            completion_tokens: row.get(4)?,
+            latency_ms: row.get(5)?,
+            cost_usd: row.get(6)?,
+            created_at: row.get(7)?,
+            indexable: row.get(8)?,
+            succeeded: row.get(9)?,
+        })
+    })?;
+
+    let mut generations = Vec::new();
+    for row in rows {
+        generations.push(row?);
+    }
+    Ok(generations)
+}
+
+pub fn get_monthly_usage(pool: &DbPool) -> Result<Vec<MonthlyUsage>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT strftime('%Y-%m', createdAt) AS month, SUM(succeeded), SUM(promptTokens), SUM(completionTokens), SUM(costUsd)
+         FROM generations GROUP BY month ORDER BY month DESC",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(MonthlyUsage {
+            month: row.get(0)?,
+            articles: row.get(1)?,
+            prompt_tokens: row.get(2)?,
+            completion_tokens: row.get(3)?,
+            cost_usd: row.get::<_, Option<f64>>(4)?.unwrap_or_default(),
+        })
+    })?;
+
+    let mut months = Vec::new();
+    for row in rows {
+        months.push(row?);
+    }
+    Ok(months)
+}
+
+/// Creates a pending job for `slug`, replacing any previous one. The caller
+/// must hold the generation lock of the slug.
+pub fn create_job(pool: &DbPool, slug: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    conn.execute(
+        "INSERT INTO jobs (slug, status) VALUES (?1, ?2)
+         ON CONFLICT(slug) DO UPDATE SET
+            status = excluded.status, error = NULL,
+            createdAt = CURRENT_TIMESTAMP, updatedAt = CURRENT_TIMESTAMP",
+        params![slug, JobStatus::Pending.as_str()],
+    )?;
+    Ok(())
+}
+
+pub fn get_job(pool: &DbPool, slug: &str) -> Result<Option<Job>, Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let result = conn.query_row(
+        "SELECT slug, status, error, updatedAt FROM jobs WHERE slug = ?1",
+        params![slug],
+        job_from_row,
+    );
+
     match result {
-        Ok(_) => Ok(true),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(false),
+        Ok(job) => Ok(Some(job)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
         Err(e) => Err(e.into()),
     }
 }
 
-pub fn create_generation_lock(pool: &DbPool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// Jobs that were pending or running when the server last stopped.
+pub fn get_unfinished_jobs(pool: &DbPool) -> Result<Vec<Job>, Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
-    conn.execute("INSERT INTO locks (title) VALUES (?1)", params!["lock"])?;
-    Ok(())
+    let mut stmt = conn.prepare(
+        "SELECT slug, status, error, updatedAt FROM jobs WHERE status IN (?1, ?2) ORDER BY createdAt",
+    )?;
+    let rows = stmt.query_map(
+        params![JobStatus::Pending.as_str(), JobStatus::Running.as_str()],
+        job_from_row,
+    )?;
+
+    let mut jobs = Vec::new();
+    for row in rows {
+        jobs.push(row?);
+    }
+    Ok(jobs)
 }
 
-pub fn insert_article(pool: &DbPool, slug: &str, title: &str, content: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub fn update_job_status(pool: &DbPool, slug: &str, status: JobStatus, error: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let conn = pool.get()?;
     conn.execute(
-        "INSERT INTO articles (slug, title, content) VALUES (?1, ?2, ?3)",
-        params![slug, title, content],
+        "UPDATE jobs SET status = ?2, error = ?3, updatedAt = CURRENT_TIMESTAMP WHERE slug = ?1",
+        params![slug, status.as_str(), error],
     )?;
     Ok(())
 }
 
-pub fn calculate_wait_time(last_article_date: &str) -> Result<i64, Box<dyn std::error::Error>> {
-    let date = NaiveDateTime::parse_from_str(last_article_date, "%Y-%m-%d %H:%M:%S")?;
-    let current_time = Local::now();
-    let offset = current_time.offset().clone();
-    let datetime = DateTime::<Local>::from_naive_utc_and_offset(date, offset) + Duration::days(1);
-    
-    let difference = datetime.signed_duration_since(current_time);
-    Ok(difference.num_hours() + 1)
-}
\ No newline at end of file
+fn job_from_row(row: &rusqlite::Row) -> rusqlite::Result<Job> {
+    let status: String = row.get(1)?;
+    Ok(Job {
+        slug: row.get(0)?,
+        status: JobStatus::from_str(&status).unwrap_or(JobStatus::Failed),
+        error: row.get(2)?,
+        updated_at: row.get(3)?,
+    })
+}
+
+/// Total tokens and known cost of the generations since `start`, an SQLite
+/// date modifier such as `'start of day'` or `'start of month'`.
+/// Tokens and cost since `start`. The cost is `None` if the price of one of
+/// the generations was unknown.
+pub fn get_usage_since(pool: &DbPool, start: &str) -> Result<(i64, Option<f64>), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = pool.get()?;
+    let usage = conn.query_row(
+        "SELECT COALESCE(SUM(promptTokens + completionTokens), 0),
+                CASE WHEN COUNT(costUsd) = COUNT(*) THEN COALESCE(SUM(costUsd), 0) END
+         FROM generations WHERE createdAt >= datetime('now', ?1)",
+        params![start],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<f64>>(1)?)),
+    )?;
+    Ok(usage)
+}
+
+/// Time left until an article created at `article_date` drops out of `window`.
+pub fn calculate_wait_time(article_date: &str, window: std::time::Duration) -> Result<Duration, Box<dyn std::error::Error>> {
+    let date = NaiveDateTime::parse_from_str(article_date, "%Y-%m-%d %H:%M:%S")?.and_utc();
+    let window = Duration::from_std(window)?;
+
+    let difference = (date + window).signed_duration_since(Utc::now());
+    Ok(difference.max(Duration::zero()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ago(duration: Duration) -> String {
+        (Utc::now() - duration).format("%Y-%m-%d %H:%M:%S").to_string()
+    }
+
+    #[test]
+    fn wait_time_is_what_is_left_of_the_window() {
+        let hour = std::time::Duration::from_secs(3600);
+        let wait = calculate_wait_time(&ago(Duration::minutes(20)), hour).unwrap();
+        assert!(wait > Duration::minutes(39) && wait <= Duration::minutes(40), "{:?}", wait);
+    }
+
+    #[test]
+    fn wait_time_is_zero_after_the_window() {
+        let hour = std::time::Duration::from_secs(3600);
+        assert_eq!(calculate_wait_time(&ago(Duration::hours(2)), hour).unwrap(), Duration::zero());
+    }
+
+    #[test]
+    fn wait_time_needs_a_valid_date() {
+        assert!(calculate_wait_time("yesterday", std::time::Duration::from_secs(60)).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use comrak::nodes::NodeValue;
+use comrak::{format_html, parse_document, Arena, ComrakOptions};
+use hyper::header::HeaderMap;
+use hyper::{Response, StatusCode};
+use tracing::{debug, error};
+
+use crate::body::{full, Body};
+use crate::config::{Config, Site};
+use crate::database::{get_articles_version, get_latest_articles, get_pool};
+use crate::models::Article;
+use crate::server::{base_url, escape_html, strip_title_hash};
+
+/// Number of articles in the feeds.
+const FEED_ENTRIES: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Rss,
+    Atom,
+}
+
+impl FeedFormat {
+    fn path(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "feed.xml",
+            FeedFormat::Atom => "atom.xml",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+        }
+    }
+}
+
+/// Serves the newest articles as RSS 2.0 or Atom. Feed readers poll, so
+/// unchanged feeds are answered with 304 Not Modified.
+pub fn handle_feed(format: FeedFormat, headers: &HeaderMap, config: &Config) -> Response<Body> {
+    let pool = get_pool();
+
+    let (count, newest) = match get_articles_version(pool) {
+        Ok(version) => version,
+        Err(e) => {
+            error!("Failed to fetch feed version: {:?}", e);
+            return error_response();
+        }
+    };
+    let updated = newest.as_deref().and_then(parse_date).unwrap_or_default();
+    let etag = format!("\"{}-{}-{}\"", format.path(), count, updated.timestamp());
+    let last_modified = updated.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    if is_not_modified(headers, &etag, updated) {
+        debug!("Feed {} not modified", format.path());
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header("ETag", &etag)
+            .header("Last-Modified", &last_modified)
+            .body(full(""))
+            .unwrap_or_else(|_| Response::new(full("")));
+    }
+
+    let articles = match get_latest_articles(pool, FEED_ENTRIES) {
+        Ok(articles) => articles,
+        Err(e) => {
+            error!("Failed to fetch feed articles: {:?}", e);
+            return error_response();
+        }
+    };
+
+    let base_url = base_url(headers, config);
+    let xml = match format {
+        FeedFormat::Rss => rss(&articles, &config.site, &base_url, updated),
+        FeedFormat::Atom => atom(&articles, &config.site, &base_url, updated),
+    };
+
+    Response::builder()
+        .header("Content-Type", format.content_type())
+        .header("ETag", &etag)
+        .header("Last-Modified", &last_modified)
+        .body(full(xml))
+        .unwrap_or_else(|_| error_response())
+}
+
+fn rss(articles: &[Article], site: &Site, base_url: &str, updated: DateTime<Utc>) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n<channel>\n");
+    xml.push_str(&format!(
+        "<title>{}</title>\n<link>{}/</link>\n<description>{}</description>\n<language>{}</language>\n\
+         <atom:link href=\"{}/_/feed.xml\" rel=\"self\" type=\"application/rss+xml\"/>\n\
+         <lastBuildDate>{}</lastBuildDate>\n",
+        escape_html(&site.name),
+        escape_html(base_url),
+        escape_html(&site.description),
+        escape_html(&site.language),
+        escape_html(base_url),
+        updated.to_rfc2822()
+    ));
+    // RSS wants "email (name)" here
+    if let Some(contact) = &site.contact {
+        let name = site.author.as_deref().unwrap_or(&site.name);
+        xml.push_str(&format!("<managingEditor>{} ({})</managingEditor>\n", escape_html(contact), escape_html(name)));
+    }
+
+    for article in articles {
+        let url = format!("{}/{}", base_url, article.slug);
+        let published = parse_date(&article.created_at).unwrap_or_default();
+        xml.push_str(&format!(
+            "<item>\n<title>{}</title>\n<link>{}</link>\n<guid isPermaLink=\"true\">{}</guid>\n\
+             <pubDate>{}</pubDate>\n<description>{}</description>\n</item>\n",
+            escape_html(&title(article)),
+            escape_html(&url),
+            escape_html(&url),
+            published.to_rfc2822(),
+            escape_html(&summary(&article.content, base_url))
+        ));
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn atom(articles: &[Article], site: &Site, base_url: &str, updated: DateTime<Utc>) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:lang=\"{}\">\n",
+        escape_html(&site.language)
+    ));
+    xml.push_str(&format!(
+        "<title>{}</title>\n<subtitle>{}</subtitle>\n<id>{}/</id>\n<link href=\"{}/\"/>\n\
+         <link rel=\"self\" href=\"{}/_/atom.xml\"/>\n<updated>{}</updated>\n",
+        escape_html(&site.name),
+        escape_html(&site.description),
+        escape_html(base_url),
+        escape_html(base_url),
+        escape_html(base_url),
+        updated.to_rfc3339()
+    ));
+    xml.push_str(&format!("<author><name>{}</name>", escape_html(site.author.as_deref().unwrap_or(&site.name))));
+    if let Some(contact) = &site.contact {
+        xml.push_str(&format!("<email>{}</email>", escape_html(contact)));
+    }
+    xml.push_str("</author>\n");
+
+    for article in articles {
+        let url = format!("{}/{}", base_url, article.slug);
+        let published = parse_date(&article.created_at).unwrap_or_default().to_rfc3339();
+        xml.push_str(&format!(
+            "<entry>\n<title>{}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<published>{}</published>\n\
+             <updated>{}</updated>\n<summary type=\"html\">{}</summary>\n</entry>\n",
+            escape_html(&title(article)),
+            escape_html(&url),
+            escape_html(&url),
+            published,
+            published,
+            escape_html(&summary(&article.content, base_url))
+        ));
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn title(article: &Article) -> String {
+    strip_title_hash(article.title.trim_matches('"'))
+}
+
+/// The first paragraph of the article, rendered to HTML.
+fn summary(content: &str, base_url: &str) -> String {
+    let paragraph = content
+        .split("\n\n")
+        .map(str::trim)
+        .find(|block| !block.is_empty() && !block.starts_with('#'))
+        .unwrap_or_default();
+
+    let arena = Arena::new();
+    let options = ComrakOptions::default();
+    let root = parse_document(&arena, paragraph, &options);
+
+    // Feed readers resolve relative links against the feed, not the site
+    for node in root.descendants() {
+        if let NodeValue::Link(link) | NodeValue::Image(link) = &mut node.data.borrow_mut().value {
+            link.url = absolute_url(&link.url, base_url);
+        }
+    }
+
+    let mut html = vec![];
+    if let Err(e) = format_html(root, &options, &mut html) {
+        error!("Failed to render feed summary: {:?}", e);
+    }
+    String::from_utf8_lossy(&html).into_owned()
+}
+
+/// Resolves `url` against the site root, articles link to each other by slug.
+fn absolute_url(url: &str, base_url: &str) -> String {
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    });
+
+    if has_scheme || url.starts_with("//") || url.starts_with('#') {
+        url.to_string()
+    } else {
+        format!("{}/{}", base_url, url.trim_start_matches('/'))
+    }
+}
+
+/// Whether the client's cached copy, identified by `If-None-Match` or else
+/// `If-Modified-Since`, is still current.
+fn is_not_modified(headers: &HeaderMap, etag: &str, updated: DateTime<Utc>) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(tags) = header("if-none-match") {
+        return tags
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == etag || tag == "*");
+    }
+
+    header("if-modified-since")
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+        .is_some_and(|since| updated.timestamp() <= since.timestamp())
+}
+
+/// Dates are stored by SQLite's CURRENT_TIMESTAMP, in UTC.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+fn error_response() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(full("Failed to generate feed"))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_links_are_absolute() {
+        let content = "# Title\n\nSee [this](some-slug), [that](/other) and [elsewhere](https://example.com/x).";
+        let html = summary(content, "https://blog.example.com");
+        assert!(html.contains("href=\"https://blog.example.com/some-slug\""), "{}", html);
+        assert!(html.contains("href=\"https://blog.example.com/other\""), "{}", html);
+        assert!(html.contains("href=\"https://example.com/x\""), "{}", html);
+    }
+
+    #[test]
+    fn absolute_url_keeps_schemes_and_fragments() {
+        assert_eq!(absolute_url("mailto:a@b.c", "http://x"), "mailto:a@b.c");
+        assert_eq!(absolute_url("//cdn.example.com/a", "http://x"), "//cdn.example.com/a");
+        assert_eq!(absolute_url("#top", "http://x"), "#top");
+        assert_eq!(absolute_url("a:b/c", "http://x"), "a:b/c");
+    }
+}
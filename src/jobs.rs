@@ -0,0 +1,189 @@
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::ai::generate_article;
+use crate::config::Config;
+use crate::database::{
+    acquire_generation_lock, get_pool, get_unfinished_jobs, insert_article, insert_generation, is_generation_locked,
+    refresh_generation_lock, release_generation_lock, update_job_status,
+};
+use crate::live::{self, LiveHandle, LiveStatus};
+use crate::models::JobStatus;
+use crate::providers::{ProviderError, StreamSink};
+
+/// Identifies this process as the owner of generation locks.
+static OWNER: OnceLock<String> = OnceLock::new();
+
+/// Exclusive right to generate one slug: within this process through the
+/// live registry, across processes sharing the database through a lock row
+/// that is kept alive while held. Both are released when dropped.
+pub struct GenerationLock {
+    slug: String,
+    live: Arc<LiveHandle>,
+    heartbeat: JoinHandle<()>,
+    /// Notified when the lock row expired and may belong to someone else now
+    lost: Arc<Notify>,
+}
+
+fn owner() -> &'static str {
+    OWNER.get_or_init(|| format!("{}-{}", std::process::id(), chrono::Utc::now().timestamp_micros()))
+}
+
+/// Takes the generation lock of `slug`, or returns `None` if the slug is
+/// being generated already.
+pub fn try_lock(slug: &str, config: &Config) -> Result<Option<GenerationLock>, Box<dyn std::error::Error + Send + Sync>> {
+    // Settle races between requests of this process without the database
+    let Some(live) = live::claim(slug) else {
+        return Ok(None);
+    };
+
+    let ttl = config.generation_lock_ttl;
+    if !acquire_generation_lock(get_pool(), slug, owner(), ttl)? {
+        return Ok(None);
+    }
+
+    let lost = Arc::new(Notify::new());
+    let heartbeat = {
+        let slug = slug.to_string();
+        let lost = lost.clone();
+        tokio::task::spawn(async move {
+            let mut interval = tokio::time::interval(ttl / 3);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match refresh_generation_lock(get_pool(), &slug, owner(), ttl) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!("Lost generation lock for slug: {}", slug);
+                        lost.notify_one();
+                        return;
+                    }
+                    Err(e) => error!("Failed to refresh generation lock for slug '{}': {:?}", slug, e),
+                }
+            }
+        })
+    };
+
+    Ok(Some(GenerationLock {
+        slug: slug.to_string(),
+        live: Arc::new(live),
+        heartbeat,
+        lost,
+    }))
+}
+
+impl Drop for GenerationLock {
+    fn drop(&mut self) {
+        self.heartbeat.abort();
+        match release_generation_lock(get_pool(), &self.slug, owner()) {
+            Ok(()) => debug!("Released generation lock for slug: {}", self.slug),
+            Err(e) => error!("Failed to release generation lock for slug '{}': {:?}", self.slug, e),
+        }
+    }
+}
+
+/// Generates the article for the locked slug in the background. The job row
+/// must have been created with `create_job` before; its status tracks the
+/// progress. The lock is released once the job is finished.
+///
+/// Losing the lock cancels the generation: whoever took it over is writing
+/// the article already, finishing ours would pay for it twice.
+pub fn start(lock: GenerationLock, config: Config) {
+    tokio::task::spawn(async move {
+        run(&lock, &config).await;
+        drop(lock);
+    });
+}
+
+/// Restarts jobs that were interrupted by a shutdown or crash, unless another
+/// process is working on them.
+pub fn resume_unfinished(config: &Config) {
+    let jobs = match get_unfinished_jobs(get_pool()) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("Failed to load unfinished jobs: {:?}", e);
+            return;
+        }
+    };
+
+    for job in jobs {
+        match try_lock(&job.slug, config) {
+            Ok(Some(lock)) => {
+                info!("Resuming {} generation job for slug: {}", job.status.as_str(), job.slug);
+                start(lock, config.clone());
+            }
+            Ok(None) => debug!("Generation of slug '{}' is locked, not resuming it", job.slug),
+            Err(e) => error!("Failed to lock slug '{}' for resuming: {:?}", job.slug, e),
+        }
+    }
+}
+
+async fn run(lock: &GenerationLock, config: &Config) {
+    let pool = get_pool();
+    let slug = lock.slug.as_str();
+
+    if let Err(e) = update_job_status(pool, slug, JobStatus::Running, None) {
+        warn!("Failed to mark job for slug '{}' as running: {:?}", slug, e);
+    }
+    info!("Starting article generation for slug: {}", slug);
+
+    let live = &lock.live;
+    let sink: Arc<StreamSink> = {
+        let live = live.clone();
+        Arc::new(move |event| live.apply(event))
+    };
+    let generation = match generate_article(slug, config, sink, &lock.lost).await {
+        Ok(generation) => generation,
+        Err(failed) => {
+            let cancelled = matches!(failed.error, ProviderError::Cancelled);
+            if cancelled {
+                error!("Cancelled generation of slug '{}', its lock was lost", slug);
+            } else {
+                error!("No content generated for slug '{}': {}", slug, failed.error);
+            }
+            // Failed attempts cost money, too
+            let spend = &failed.spend;
+            if spend.usage.prompt_tokens + spend.usage.completion_tokens > 0 {
+                if let Err(e) = insert_generation(pool, slug, spend, false) {
+                    error!("Failed to store generation metadata for slug '{}': {:?}", slug, e);
+                }
+            }
+            // The job belongs to whoever took over the lock, if anyone did
+            if cancelled && is_generation_locked(pool, slug).unwrap_or(true) {
+                return;
+            }
+            let error = if cancelled { "Generation lock lost".to_string() } else { failed.error.to_string() };
+            if let Err(e) = update_job_status(pool, slug, JobStatus::Failed, Some(&error)) {
+                error!("Failed to mark job for slug '{}' as failed: {:?}", slug, e);
+            }
+            return;
+        }
+    };
+
+    let content = &generation.content;
+    let status = match insert_article(pool, slug, &content.title, &content.content, config.index_new_articles) {
+        Ok(_) => {
+            info!(
+                "Successfully stored article for slug: {} (written by {} {})",
+                slug, generation.spend.provider, generation.spend.model
+            );
+            live.finish(LiveStatus::Done);
+            JobStatus::Done
+        }
+        Err(e) => {
+            error!("Failed to store article for slug '{}': {:?}", slug, e);
+            JobStatus::Failed
+        }
+    };
+
+    if let Err(e) = insert_generation(pool, slug, &generation.spend, true) {
+        error!("Failed to store generation metadata for slug '{}': {:?}", slug, e);
+    }
+
+    let error = (status == JobStatus::Failed).then_some("Failed to store article");
+    if let Err(e) = update_job_status(pool, slug, status, error) {
+        error!("Failed to update job for slug '{}': {:?}", slug, e);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::watch;
+
+use crate::models::StreamEvent;
+
+/// Articles currently being generated by this process, so concurrent requests
+/// for the same slug follow one generation and visitors can watch it.
+static LIVE: OnceLock<Mutex<HashMap<String, Arc<watch::Sender<LiveArticle>>>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiveStatus {
+    Writing,
+    Done,
+    Failed,
+}
+
+/// What has been written so far of an article in the making.
+#[derive(Debug, Clone)]
+pub struct LiveArticle {
+    pub title: Option<String>,
+    pub text: String,
+    pub status: LiveStatus,
+}
+
+/// Publishes the progress of one generation. Dropping the handle removes the
+/// article from the registry, marking it as failed if it never finished.
+pub struct LiveHandle {
+    slug: String,
+    sender: Arc<watch::Sender<LiveArticle>>,
+}
+
+fn live() -> &'static Mutex<HashMap<String, Arc<watch::Sender<LiveArticle>>>> {
+    LIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Makes this process the one generating `slug`. Returns `None` if the slug
+/// is being generated already; follow that generation instead.
+pub fn claim(slug: &str) -> Option<LiveHandle> {
+    let mut live = live().lock().unwrap_or_else(|e| e.into_inner());
+    if live.contains_key(slug) {
+        return None;
+    }
+
+    let (sender, _) = watch::channel(LiveArticle {
+        title: None,
+        text: String::new(),
+        status: LiveStatus::Writing,
+    });
+    let sender = Arc::new(sender);
+    live.insert(slug.to_string(), sender.clone());
+
+    Some(LiveHandle {
+        slug: slug.to_string(),
+        sender,
+    })
+}
+
+pub fn is_live(slug: &str) -> bool {
+    live().lock().unwrap_or_else(|e| e.into_inner()).contains_key(slug)
+}
+
+/// Follows the generation of `slug`, if this process is writing it right now.
+pub fn subscribe(slug: &str) -> Option<watch::Receiver<LiveArticle>> {
+    live()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(slug)
+        .map(|sender| sender.subscribe())
+}
+
+impl LiveHandle {
+    pub fn apply(&self, event: StreamEvent) {
+        self.sender.send_modify(|article| match event {
+            StreamEvent::Title(title) => article.title = Some(title),
+            StreamEvent::Delta(text) => article.text.push_str(&text),
+            StreamEvent::Restart => article.text.clear(),
+        });
+    }
+
+    pub fn finish(&self, status: LiveStatus) {
+        self.sender.send_modify(|article| article.status = status);
+    }
+}
+
+impl Drop for LiveHandle {
+    fn drop(&mut self) {
+        self.sender.send_if_modified(|article| {
+            let unfinished = article.status == LiveStatus::Writing;
+            if unfinished {
+                article.status = LiveStatus::Failed;
+            }
+            unfinished
+        });
+
+        live().lock().unwrap_or_else(|e| e.into_inner()).remove(&self.slug);
+    }
+}
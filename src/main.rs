@@ -1,10 +1,23 @@
 #![deny(warnings)]
 
 mod ai;
+mod body;
+mod budget;
 mod config;
 mod database;
+mod feed;
+mod jobs;
+mod live;
+mod migrations;
 mod models;
+mod pricing;
+mod providers;
+mod router;
 mod server;
+mod sitemap;
+mod stream;
+mod theme;
+mod throttle;
 
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
@@ -22,14 +35,27 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env().add_directive("autoblogger=info".parse()?))
         .init();
 
+    // `autoblogger migrate [status]` manages the database schema without starting the server
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        return migrate_command(args.get(1).map(String::as_str));
+    }
+
     // Load and validate configuration
     let config = Config::from_env()
         .map_err(|e| format!("Configuration error: {}", e))?;
+    info!("Using {} model {}", config.model.provider.as_str(), config.model.model_id);
+
+    theme::init(&config)
+        .map_err(|e| format!("Theme error: {}", e))?;
 
     // Initialize database with config
     database::init_pool_with_config(&config)
         .map_err(|e| format!("Database initialization error: {}", e))?;
 
+    // Pick up generations that were interrupted by the last shutdown
+    jobs::resume_unfinished(&config);
+
     // This address is localhost
     let addr: SocketAddr = ([0, 0, 0, 0], config.server_port).into();
 
@@ -45,7 +71,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // has work to do. In this case, a connection arrives on the port we are listening on and
         // the task is woken up, at which point the task is then put back on a thread, and is
         // driven forward by the runtime, eventually yielding a TCP stream.
-        let (stream, _) = listener.accept().await?;
+        let (stream, remote_addr) = listener.accept().await?;
 
         let io = TokioIo::new(stream);
 
@@ -58,7 +84,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             // HTTP requests received on that connection to the `server::handle_request` function
             if let Err(err) = http1::Builder::new()
                 .serve_connection(io, service_fn(move |req| {
-                    server::handle_request(req, config_clone.clone())
+                    server::handle_request(req, remote_addr, config_clone.clone())
                 }))
                 .await
             {
@@ -66,4 +92,26 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             }
         });
     }
-}
\ No newline at end of file
+}
+
+fn migrate_command(subcommand: Option<&str>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let db_path = Config::db_path_from_env();
+    let mut conn = rusqlite::Connection::open(&db_path)?;
+
+    match subcommand {
+        None | Some("up") => {
+            let applied = migrations::run(&mut conn)?;
+            println!("Applied {} migration(s), {} is at version {}", applied, db_path, migrations::current_version(&conn)?);
+        }
+        Some("status") => {
+            println!("{} is at version {}", db_path, migrations::current_version(&conn)?);
+            for migration in migrations::status(&conn)? {
+                let state = migration.applied_at.unwrap_or_else(|| "pending".to_string());
+                println!("{:>4}  {:<40} {}", migration.version, migration.name, state);
+            }
+        }
+        Some(other) => return Err(format!("Unknown migrate command '{}', use 'up' or 'status'", other).into()),
+    }
+
+    Ok(())
+}
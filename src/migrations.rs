@@ -0,0 +1,233 @@
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use tracing::info;
+
+/// A schema change, applied once and recorded in `schema_version`.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All migrations in the order they are applied. Never change or remove one
+/// that has been released, add a new one instead.
+///
+/// The first ones create tables that existed before there were migrations,
+/// hence `IF NOT EXISTS`.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create articles",
+        sql: "CREATE TABLE IF NOT EXISTS articles (
+            slug     TEXT PRIMARY KEY,
+            title    TEXT NOT NULL,
+            content  TEXT NOT NULL,
+            createdAt DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    },
+    Migration {
+        version: 2,
+        name: "create generations",
+        sql: "CREATE TABLE IF NOT EXISTS generations (
+            slug             TEXT PRIMARY KEY,
+            provider         TEXT NOT NULL,
+            model            TEXT NOT NULL,
+            promptTokens     INTEGER NOT NULL,
+            completionTokens INTEGER NOT NULL,
+            latencyMs        INTEGER NOT NULL,
+            costUsd          REAL,
+            createdAt        DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    },
+    Migration {
+        version: 3,
+        name: "create jobs",
+        sql: "CREATE TABLE IF NOT EXISTS jobs (
+            slug      TEXT PRIMARY KEY,
+            status    TEXT NOT NULL,
+            error     TEXT,
+            createdAt DATETIME DEFAULT CURRENT_TIMESTAMP,
+            updatedAt DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+    },
+    Migration {
+        version: 4,
+        name: "replace locks with generation_locks",
+        sql: "CREATE TABLE IF NOT EXISTS generation_locks (
+            slug      TEXT PRIMARY KEY,
+            owner     TEXT NOT NULL,
+            createdAt DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expiresAt DATETIME NOT NULL
+        );
+        DROP TABLE IF EXISTS locks;",
+    },
+    Migration {
+        version: 5,
+        name: "add full-text search index",
+        sql: "CREATE VIRTUAL TABLE articles_fts USING fts5(
+            title, content, content = 'articles', content_rowid = 'rowid'
+        );
+        INSERT INTO articles_fts (articles_fts) VALUES ('rebuild');
+        CREATE TRIGGER articles_fts_insert AFTER INSERT ON articles BEGIN
+            INSERT INTO articles_fts (rowid, title, content) VALUES (new.rowid, new.title, new.content);
+        END;
+        CREATE TRIGGER articles_fts_delete AFTER DELETE ON articles BEGIN
+            INSERT INTO articles_fts (articles_fts, rowid, title, content) VALUES ('delete', old.rowid, old.title, old.content);
+        END;
+        CREATE TRIGGER articles_fts_update AFTER UPDATE ON articles BEGIN
+            INSERT INTO articles_fts (articles_fts, rowid, title, content) VALUES ('delete', old.rowid, old.title, old.content);
+            INSERT INTO articles_fts (rowid, title, content) VALUES (new.rowid, new.title, new.content);
+        END;",
+    },
+    Migration {
+        version: 6,
+        name: "add indexable flag to articles",
+        sql: "ALTER TABLE articles ADD COLUMN indexable INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE articles ADD COLUMN updatedAt DATETIME;",
+    },
+    Migration {
+        version: 7,
+        name: "record every generation attempt",
+        sql: "CREATE TABLE generations_new (
+            id               INTEGER PRIMARY KEY,
+            slug             TEXT NOT NULL,
+            provider         TEXT NOT NULL,
+            model            TEXT NOT NULL,
+            promptTokens     INTEGER NOT NULL,
+            completionTokens INTEGER NOT NULL,
+            latencyMs        INTEGER NOT NULL,
+            costUsd          REAL,
+            succeeded        INTEGER NOT NULL DEFAULT 1,
+            createdAt        DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        INSERT INTO generations_new (slug, provider, model, promptTokens, completionTokens, latencyMs, costUsd, createdAt)
+            SELECT slug, provider, model, promptTokens, completionTokens, latencyMs, costUsd, createdAt FROM generations;
+        DROP TABLE generations;
+        ALTER TABLE generations_new RENAME TO generations;
+        CREATE INDEX generations_slug ON generations (slug);
+        CREATE INDEX generations_createdAt ON generations (createdAt);",
+    },
+];
+
+/// State of one migration in a database.
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    /// When it was applied, `None` if it is pending
+    pub applied_at: Option<String>,
+}
+
+/// Applies all pending migrations, each in its own transaction. Returns the
+/// number of migrations applied.
+pub fn run(conn: &mut Connection) -> Result<usize, rusqlite::Error> {
+    create_version_table(conn)?;
+    let current = current_version(conn)?;
+    let mut applied = 0;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        info!("Applying migration {}: {}", migration.version, migration.name);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(
+            "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+            params![migration.version, migration.name],
+        )?;
+        tx.commit()?;
+        applied += 1;
+    }
+
+    Ok(applied)
+}
+
+/// Lists every known migration and whether it has been applied, without
+/// changing the database.
+pub fn status(conn: &Connection) -> Result<Vec<MigrationStatus>, rusqlite::Error> {
+    let mut applied = HashMap::new();
+    if has_version_table(conn)? {
+        let mut stmt = conn.prepare("SELECT version, appliedAt FROM schema_version")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<usize, i64>(0)?, row.get::<usize, String>(1)?)))?;
+        for row in rows {
+            let (version, applied_at) = row?;
+            applied.insert(version, applied_at);
+        }
+    }
+
+    Ok(MIGRATIONS
+        .iter()
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            name: migration.name,
+            applied_at: applied.remove(&migration.version),
+        })
+        .collect())
+}
+
+/// The version of the last applied migration, 0 for a database without any.
+pub fn current_version(conn: &Connection) -> Result<i64, rusqlite::Error> {
+    if !has_version_table(conn)? {
+        return Ok(0);
+    }
+    conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))
+}
+
+fn has_version_table(conn: &Connection) -> Result<bool, rusqlite::Error> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn create_version_table(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version   INTEGER PRIMARY KEY,
+            name      TEXT NOT NULL,
+            appliedAt DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        (),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_generations_and_allows_several_per_slug() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_version_table(&conn).unwrap();
+        for migration in MIGRATIONS.iter().filter(|m| m.version < 7) {
+            conn.execute_batch(migration.sql).unwrap();
+            conn.execute(
+                "INSERT INTO schema_version (version, name) VALUES (?1, ?2)",
+                params![migration.version, migration.name],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO generations (slug, provider, model, promptTokens, completionTokens, latencyMs, costUsd)
+             VALUES ('slug', 'mock', 'mock', 1, 2, 3, 0.5)",
+            (),
+        )
+        .unwrap();
+
+        assert_eq!(run(&mut conn).unwrap(), MIGRATIONS.len() - 6);
+        conn.execute(
+            "INSERT INTO generations (slug, provider, model, promptTokens, completionTokens, latencyMs, succeeded)
+             VALUES ('slug', 'mock', 'mock', 4, 5, 6, 0)",
+            (),
+        )
+        .unwrap();
+
+        let (rows, tokens, succeeded): (i64, i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(*), SUM(promptTokens + completionTokens), SUM(succeeded) FROM generations WHERE slug = 'slug'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!((rows, tokens, succeeded), (2, 12, 1));
+    }
+}
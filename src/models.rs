@@ -19,6 +19,13 @@ pub struct GptCompletion {
     pub created: i64,
     pub model: String,
     pub choices: Vec<Choice>,
+    pub usage: Option<GptUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GptUsage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,6 +38,13 @@ pub struct AnthropicCompletion {
     pub content: Vec<AnthropicContent>,
     pub model: String,
     pub role: String,
+    pub usage: Option<AnthropicUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnthropicUsage {
+    pub input_tokens: i64,
+    pub output_tokens: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,10 +52,221 @@ pub struct RequestBody {
     pub model: String,
     pub messages: Vec<Message>,
     pub max_tokens: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StreamOptions {
+    pub include_usage: bool,
+}
+
+/// One chunk of a streamed OpenAI chat completion.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GptChunk {
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<GptChunkChoice>,
+    pub usage: Option<GptUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GptChunkChoice {
+    pub delta: GptDelta,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GptDelta {
+    pub content: Option<String>,
+}
+
+/// The events of a streamed Anthropic message we care about.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicStreamEvent {
+    MessageStart { message: AnthropicStreamMessage },
+    ContentBlockDelta { delta: AnthropicDelta },
+    MessageDelta { usage: AnthropicOutputUsage },
+    Error { error: AnthropicStreamError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnthropicStreamMessage {
+    pub model: String,
+    pub usage: Option<AnthropicUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnthropicDelta {
+    TextDelta { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnthropicOutputUsage {
+    pub output_tokens: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AnthropicStreamError {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub message: String,
+}
+
+/// Progress of an article generation as it happens.
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Title(String),
+    /// More text of the article
+    Delta(String),
+    /// The text so far is void, e.g. because a retry or fallback starts over
+    Restart,
 }
 
 #[derive(Debug)]
 pub struct Content {
     pub title: String,
     pub content: String,
-}
\ No newline at end of file
+}
+
+/// Token counts reported by the provider for one or more completions.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+        }
+    }
+}
+
+/// What writing an article took, whether or not it succeeded. Usage,
+/// latency and cost cover the title request and every article attempt.
+#[derive(Debug, Clone)]
+pub struct Spend {
+    /// Provider and model of the last request
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+    pub latency_ms: i64,
+    /// `None` when the price of a model is unknown
+    pub cost_usd: Option<f64>,
+}
+
+/// A freshly generated article and what it took to write it.
+#[derive(Debug)]
+pub struct Generation {
+    pub content: Content,
+    pub spend: Spend,
+}
+
+/// A stored [`Generation`], as shown in the admin view.
+#[derive(Debug)]
+pub struct GenerationRecord {
+    pub slug: String,
+    pub provider: String,
+    pub model: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub latency_ms: i64,
+    pub cost_usd: Option<f64>,
+    pub created_at: String,
+    /// Whether the article may be indexed, `None` if it no longer exists
+    pub indexable: Option<bool>,
+    /// Whether an article came out of it
+    pub succeeded: bool,
+}
+
+/// A stored article.
+#[derive(Debug)]
+pub struct Article {
+    pub slug: String,
+    pub title: String,
+    pub content: String,
+    pub created_at: String,
+    /// Search engines may index it, if indexing is enabled at all
+    pub indexable: bool,
+}
+
+/// An entry of the article archive.
+#[derive(Debug)]
+pub struct ArticleSummary {
+    pub slug: String,
+    pub title: String,
+    pub created_at: String,
+}
+
+/// An article matching a search. Matches in `title` and `snippet` are
+/// enclosed in [`MATCH_START`] and [`MATCH_END`].
+#[derive(Serialize, Debug)]
+pub struct SearchResult {
+    pub slug: String,
+    pub title: String,
+    pub snippet: String,
+}
+
+pub const MATCH_START: char = '\u{2}';
+pub const MATCH_END: char = '\u{3}';
+
+#[derive(Debug)]
+pub struct MonthlyUsage {
+    pub month: String,
+    pub articles: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Sum of the known costs; generations with unknown prices count as zero
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(JobStatus::Pending),
+            "running" => Some(JobStatus::Running),
+            "done" => Some(JobStatus::Done),
+            "failed" => Some(JobStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A background article generation, persisted in the `jobs` table.
+#[derive(Debug)]
+pub struct Job {
+    pub slug: String,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    pub updated_at: String,
+}
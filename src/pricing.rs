@@ -0,0 +1,44 @@
+use crate::config::{Config, ModelPrice};
+use crate::models::Usage;
+
+/// List prices in USD per million tokens, matched by model id prefix. API
+/// responses often name a dated snapshot (`gpt-4o-2024-08-06`), hence prefixes.
+/// Prices change; `AI_PRICING` overrides or extends this table.
+const DEFAULT_PRICES: &[(&str, ModelPrice)] = &[
+    ("gpt-4o-mini", ModelPrice { input_per_mtok: 0.15, output_per_mtok: 0.6 }),
+    ("gpt-4o", ModelPrice { input_per_mtok: 2.5, output_per_mtok: 10.0 }),
+    ("gpt-5-mini", ModelPrice { input_per_mtok: 0.25, output_per_mtok: 2.0 }),
+    ("gpt-5", ModelPrice { input_per_mtok: 1.25, output_per_mtok: 10.0 }),
+    ("claude-3-7-sonnet", ModelPrice { input_per_mtok: 3.0, output_per_mtok: 15.0 }),
+    ("claude-sonnet-4", ModelPrice { input_per_mtok: 3.0, output_per_mtok: 15.0 }),
+    ("mock", ModelPrice { input_per_mtok: 0.0, output_per_mtok: 0.0 }),
+];
+
+/// Estimated cost in USD, or `None` if no price is known for `model`.
+pub fn estimate_cost(config: &Config, model: &str, usage: Usage) -> Option<f64> {
+    let price = find_price(config, model)?;
+    let cost = usage.prompt_tokens as f64 * price.input_per_mtok
+        + usage.completion_tokens as f64 * price.output_per_mtok;
+    Some(cost / 1_000_000.0)
+}
+
+/// Whether the cost of `model` can be estimated.
+pub fn has_price(config: &Config, model: &str) -> bool {
+    find_price(config, model).is_some()
+}
+
+fn find_price(config: &Config, model: &str) -> Option<ModelPrice> {
+    let configured = config.pricing.iter().map(|(prefix, price)| (prefix.as_str(), *price));
+    let defaults = DEFAULT_PRICES.iter().map(|(prefix, price)| (*prefix, *price));
+
+    // Configured prices win over defaults, longer prefixes over shorter ones
+    configured
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .or_else(|| {
+            defaults
+                .filter(|(prefix, _)| model.starts_with(prefix))
+                .max_by_key(|(prefix, _)| prefix.len())
+        })
+        .map(|(_, price)| price)
+}
@@ -0,0 +1,129 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue};
+use tracing::debug;
+
+use super::{
+    parse_event, send_json, send_sse, Completion, CompletionOptions, LlmProvider, ProviderError, ProviderResult,
+    StreamSink,
+};
+use crate::config::AiProvider;
+use crate::models::{
+    AnthropicCompletion, AnthropicDelta, AnthropicStreamEvent, Message, RequestBody, StreamEvent, Usage,
+};
+
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+
+pub struct AnthropicProvider {
+    api_key: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl AnthropicProvider {
+    pub fn new(client: reqwest::Client, api_key: &str, model: &str) -> Self {
+        AnthropicProvider {
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            client,
+        }
+    }
+
+    fn headers(&self) -> ProviderResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_str(&self.api_key)?);
+        headers.insert("anthropic-version", HeaderValue::from_str("2023-06-01")?);
+        headers.insert("content-type", HeaderValue::from_str("application/json")?);
+        Ok(headers)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn name(&self) -> &'static str {
+        "Claude"
+    }
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion> {
+        let body = RequestBody {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: options.max_tokens,
+            stream: None,
+            stream_options: None,
+        };
+
+        let request = self.client.post(API_URL).headers(self.headers()?).json(&body);
+        let response = send_json::<AnthropicCompletion>(request).await?;
+
+        debug!("Successfully received completion from Claude");
+        let text = response
+            .content
+            .first()
+            .map(|c| c.text.clone())
+            .ok_or_else(|| ProviderError::MalformedResponse("Claude returned no content".to_string()))?;
+        Ok(Completion {
+            text,
+            provider: AiProvider::Anthropic.as_str().to_string(),
+            model: response.model,
+            usage: response
+                .usage
+                .map(|u| Usage {
+                    prompt_tokens: u.input_tokens,
+                    completion_tokens: u.output_tokens,
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn stream(&self, messages: &[Message], options: &CompletionOptions, sink: &StreamSink) -> ProviderResult<Completion> {
+        let body = RequestBody {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: options.max_tokens,
+            stream: Some(true),
+            stream_options: None,
+        };
+
+        let mut text = String::new();
+        let mut model = self.model.clone();
+        let mut usage = Usage::default();
+
+        let request = self.client.post(API_URL).headers(self.headers()?).json(&body);
+        send_sse(request, |_, data| {
+            match parse_event::<AnthropicStreamEvent>(self.name(), data)? {
+                AnthropicStreamEvent::MessageStart { message } => {
+                    model = message.model;
+                    if let Some(u) = message.usage {
+                        usage.prompt_tokens = u.input_tokens;
+                    }
+                }
+                AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::TextDelta { text: delta } } => {
+                    text.push_str(&delta);
+                    sink(StreamEvent::Delta(delta));
+                }
+                AnthropicStreamEvent::MessageDelta { usage: u } => usage.completion_tokens = u.output_tokens,
+                AnthropicStreamEvent::Error { error } => {
+                    return Err(match error.kind.as_str() {
+                        "overloaded_error" => ProviderError::Overloaded { retry_after: None },
+                        "rate_limit_error" => ProviderError::RateLimited { retry_after: None },
+                        _ => ProviderError::MalformedResponse(format!("{}: {}", error.kind, error.message)),
+                    });
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+        .await?;
+
+        debug!("Successfully streamed completion from Claude");
+        if text.is_empty() {
+            return Err(ProviderError::MalformedResponse("Claude returned no content".to_string()));
+        }
+        Ok(Completion {
+            text,
+            provider: AiProvider::Anthropic.as_str().to_string(),
+            model,
+            usage,
+        })
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+/// Why a completion could not be produced. Transient failures can be retried
+/// (see [`ProviderError::is_retryable`]), the others need someone to act.
+#[derive(Debug)]
+pub enum ProviderError {
+    /// HTTP 429, the account hit its rate limit
+    RateLimited { retry_after: Option<Duration> },
+    /// HTTP 529 (Anthropic) or 503, the provider is overloaded
+    Overloaded { retry_after: Option<Duration> },
+    /// HTTP 401/403, the API key is missing, invalid or lacks permissions
+    Auth(String),
+    /// The request did not complete within the configured timeout
+    Timeout,
+    /// The provider answered, but not with something we can use
+    MalformedResponse(String),
+    /// Any other unsuccessful HTTP status
+    Http { status: u16, body: String },
+    /// Connection level failures (DNS, refused, reset, ...)
+    Network(String),
+    /// The provider could not be set up from the configuration
+    Config(String),
+    /// The request was abandoned before it finished
+    Cancelled,
+}
+
+impl ProviderError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProviderError::RateLimited { .. }
+            | ProviderError::Overloaded { .. }
+            | ProviderError::Timeout
+            | ProviderError::Network(_) => true,
+            ProviderError::Http { status, .. } => *status >= 500,
+            ProviderError::Auth(_)
+            | ProviderError::MalformedResponse(_)
+            | ProviderError::Config(_)
+            | ProviderError::Cancelled => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ProviderError::RateLimited { retry_after } | ProviderError::Overloaded { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Classifies an unsuccessful HTTP response.
+    pub fn from_response(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let retry_after = parse_retry_after(headers);
+        match status.as_u16() {
+            429 => ProviderError::RateLimited { retry_after },
+            503 | 529 => ProviderError::Overloaded { retry_after },
+            401 | 403 => ProviderError::Auth(body),
+            status => ProviderError::Http { status, body },
+        }
+    }
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::RateLimited { .. } => write!(f, "rate limited by the provider"),
+            ProviderError::Overloaded { .. } => write!(f, "the provider is overloaded"),
+            ProviderError::Auth(body) => write!(f, "authentication failed: {}", body),
+            ProviderError::Timeout => write!(f, "request timed out"),
+            ProviderError::MalformedResponse(msg) => write!(f, "malformed response: {}", msg),
+            ProviderError::Http { status, body } => write!(f, "unexpected HTTP status {}: {}", status, body),
+            ProviderError::Network(msg) => write!(f, "network error: {}", msg),
+            ProviderError::Config(msg) => write!(f, "configuration error: {}", msg),
+            ProviderError::Cancelled => write!(f, "request cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            ProviderError::Timeout
+        } else if err.is_decode() {
+            ProviderError::MalformedResponse(err.to_string())
+        } else {
+            ProviderError::Network(err.to_string())
+        }
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for ProviderError {
+    fn from(err: reqwest::header::InvalidHeaderValue) -> Self {
+        ProviderError::Config(format!("invalid header value: {}", err))
+    }
+}
+
+/// Delays too long to represent, like `inf`, are as good as forever; NaN and
+/// negative ones mean no delay.
+fn from_secs(seconds: f64) -> Duration {
+    Duration::try_from_secs_f64(seconds.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+/// Reads `retry-after-ms` (OpenAI) or `retry-after` in either of its
+/// forms: delay in seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.parse::<f64>().ok()) {
+        return Some(from_secs(ms / 1000.0));
+    }
+
+    let value = header("retry-after")?;
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&Utc).signed_duration_since(Utc::now());
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry_after(name: &'static str, value: &'static str) -> Option<Duration> {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, value.parse().unwrap());
+        parse_retry_after(&headers)
+    }
+
+    #[test]
+    fn parses_delays() {
+        assert_eq!(retry_after("retry-after", "2"), Some(Duration::from_secs(2)));
+        assert_eq!(retry_after("retry-after-ms", "1500"), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_after("retry-after", "-5"), Some(Duration::ZERO));
+        assert_eq!(retry_after("retry-after", "NaN"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn huge_delays_do_not_panic() {
+        for value in ["inf", "1e400", "1e30"] {
+            assert_eq!(retry_after("retry-after", value), Some(Duration::MAX));
+            assert_eq!(retry_after("retry-after-ms", value), Some(Duration::MAX));
+        }
+    }
+}
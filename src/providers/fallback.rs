@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use std::future::Future;
+use std::pin::Pin;
+use tracing::warn;
+
+use super::{Completion, CompletionOptions, LlmProvider, ProviderError, ProviderResult, StreamSink};
+use crate::models::{Message, StreamEvent};
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Tries each provider in order until one of them produces a completion.
+///
+/// The returned [`Completion`] names the provider and model that answered;
+/// if every provider fails, the error of the last one is returned.
+pub struct FallbackProvider {
+    chain: Vec<Box<dyn LlmProvider>>,
+}
+
+impl FallbackProvider {
+    pub fn new(chain: Vec<Box<dyn LlmProvider>>) -> Self {
+        FallbackProvider { chain }
+    }
+}
+
+impl FallbackProvider {
+    async fn walk<'a, F>(&'a self, mut request: F) -> ProviderResult<Completion>
+    where
+        F: FnMut(&'a dyn LlmProvider) -> BoxFuture<'a, ProviderResult<Completion>>,
+    {
+        let mut last_error = None;
+
+        for (i, provider) in self.chain.iter().enumerate() {
+            match request(provider.as_ref()).await {
+                Ok(completion) => return Ok(completion),
+                Err(err) => {
+                    if let Some(next) = self.chain.get(i + 1) {
+                        warn!("{} failed ({}), falling back to {}", provider.name(), err, next.name());
+                    }
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| ProviderError::Config("no providers configured".to_string())))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackProvider {
+    fn name(&self) -> &'static str {
+        "fallback chain"
+    }
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion> {
+        self.walk(|provider| provider.complete(messages, options)).await
+    }
+
+    async fn stream(&self, messages: &[Message], options: &CompletionOptions, sink: &StreamSink) -> ProviderResult<Completion> {
+        let mut first = true;
+        self.walk(|provider| {
+            if !std::mem::take(&mut first) {
+                sink(StreamEvent::Restart);
+            }
+            provider.stream(messages, options, sink)
+        })
+        .await
+    }
+}
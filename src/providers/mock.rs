@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use tracing::debug;
+
+use super::{Completion, CompletionOptions, LlmProvider, ProviderError, ProviderResult, Purpose};
+use crate::ai::{capitalize_words, unslugify};
+use crate::config::AiProvider;
+use crate::models::{Message, Usage};
+
+const WORDS: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do",
+    "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua", "enim",
+    "ad", "minim", "veniam", "quis", "nostrud", "exercitation", "ullamco", "laboris", "nisi",
+    "aliquip", "ex", "ea", "commodo", "consequat", "duis", "aute", "irure", "in", "reprehenderit",
+    "voluptate", "velit", "esse", "cillum", "fugiat", "nulla", "pariatur", "excepteur", "sint",
+    "occaecat", "cupidatat", "non", "proident", "sunt", "culpa", "qui", "officia", "deserunt",
+    "mollit", "anim", "id", "est", "laborum",
+];
+
+/// Deterministic stand-in for a real LLM, for local development, demos and tests.
+///
+/// Titles are derived from the slug, articles are lorem ipsum with inline slug
+/// links. The output only depends on the prompt subject and the configured seed.
+pub struct MockProvider {
+    model: String,
+    seed: u64,
+}
+
+impl MockProvider {
+    pub fn new(model: &str, seed: u64) -> Self {
+        MockProvider {
+            model: model.to_string(),
+            seed,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "Mock"
+    }
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion> {
+        let subject = prompt_subject(messages).ok_or_else(|| {
+            ProviderError::MalformedResponse("Mock provider could not find a subject in the prompt".to_string())
+        })?;
+        debug!("Mock provider answering {:?} prompt for '{}'", options.purpose, subject);
+
+        let text = match options.purpose {
+            Purpose::Title => capitalize_words(&unslugify(subject)),
+            Purpose::Article => lorem_article(&mut Rng::new(hash(subject) ^ self.seed)),
+        };
+
+        // Roughly four characters per token, so usage reports look plausible
+        let prompt_chars: usize = messages.iter().map(|m| m.content.len()).sum();
+        let usage = Usage {
+            prompt_tokens: (prompt_chars / 4) as i64,
+            completion_tokens: (text.len() / 4) as i64,
+        };
+
+        Ok(Completion {
+            text,
+            provider: AiProvider::Mock.as_str().to_string(),
+            model: self.model.clone(),
+            usage,
+        })
+    }
+}
+
+/// The prompts in `ai.rs` quote the slug or title they are about, so the text
+/// between the first and the last single quote of the last message is the subject.
+fn prompt_subject(messages: &[Message]) -> Option<&str> {
+    let prompt = &messages.last()?.content;
+    let start = prompt.find('\'')? + 1;
+    let end = prompt.rfind('\'')?;
+    prompt.get(start..end).filter(|s| !s.is_empty())
+}
+
+fn lorem_article(rng: &mut Rng) -> String {
+    let paragraphs = 4 + rng.below(3);
+    let mut article = Vec::with_capacity(paragraphs);
+
+    for i in 0..paragraphs {
+        if i == 2 {
+            article.push(format!("## {}", capitalize_words(&rng.words(3))));
+        }
+
+        let mut sentences = Vec::new();
+        for _ in 0..3 + rng.below(3) {
+            sentences.push(rng.sentence());
+        }
+
+        // One link per paragraph, so every mock article leads to a few more
+        let length = 2 + rng.below(2);
+        let phrase = rng.words(length);
+        let link = format!("[{}]({})", phrase, phrase.replace(' ', "-"));
+        let position = rng.below(sentences.len());
+        sentences[position] = format!("{} {}.", sentences[position].trim_end_matches('.'), link);
+
+        article.push(sentences.join(" "));
+    }
+
+    article.join("\n\n")
+}
+
+fn hash(s: &str) -> u64 {
+    // FNV-1a, stable across runs and platforms unlike the std hasher
+    s.bytes().fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Small splitmix64 generator; good enough for picking lorem ipsum words.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn words(&mut self, count: usize) -> String {
+        (0..count)
+            .map(|_| WORDS[self.below(WORDS.len())])
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn sentence(&mut self) -> String {
+        let count = 6 + self.below(10);
+        let mut sentence = self.words(count);
+        if let Some(first) = sentence.get_mut(0..1) {
+            first.make_ascii_uppercase();
+        }
+        sentence.push('.');
+        sentence
+    }
+}
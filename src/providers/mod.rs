@@ -0,0 +1,197 @@
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+
+use crate::config::{AiProvider, Config, ModelSpec};
+use crate::models::{Message, StreamEvent, Usage};
+
+mod anthropic;
+mod error;
+mod fallback;
+mod mock;
+mod openai;
+mod retry;
+
+pub use anthropic::AnthropicProvider;
+pub use error::ProviderError;
+pub use fallback::FallbackProvider;
+pub use mock::MockProvider;
+pub use openai::OpenAiProvider;
+pub use retry::RetryingProvider;
+
+pub type ProviderResult<T> = Result<T, ProviderError>;
+
+/// Receives the text of a streamed completion as it arrives.
+pub type StreamSink = dyn Fn(StreamEvent) + Send + Sync;
+
+/// What a completion is going to be used for. Real backends ignore it, the
+/// mock provider uses it to decide what kind of canned answer to produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    Title,
+    Article,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompletionOptions {
+    pub max_tokens: i64,
+    pub purpose: Purpose,
+}
+
+impl CompletionOptions {
+    pub fn for_purpose(purpose: Purpose) -> Self {
+        CompletionOptions {
+            max_tokens: 2000,
+            purpose,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    /// The provider that produced the completion, as [`AiProvider::as_str`] names it
+    pub provider: String,
+    pub model: String,
+    pub usage: Usage,
+}
+
+/// A backend able to turn a chat transcript into a completion.
+///
+/// New backends implement this trait and get registered in [`from_config`];
+/// the prompt handling in `ai.rs` never needs to know which one is in use.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion>;
+
+    /// Like [`LlmProvider::complete`], but reports the text to `sink` while it
+    /// is generated. Backends without streaming send it all at once.
+    async fn stream(&self, messages: &[Message], options: &CompletionOptions, sink: &StreamSink) -> ProviderResult<Completion> {
+        let completion = self.complete(messages, options).await?;
+        sink(StreamEvent::Delta(completion.text.clone()));
+        Ok(completion)
+    }
+}
+
+/// Builds the configured provider. With fallback models configured, this is
+/// a [`FallbackProvider`] walking the whole chain.
+pub fn from_config(config: &Config) -> ProviderResult<Box<dyn LlmProvider>> {
+    if config.fallback_models.is_empty() {
+        return build(&config.model, config);
+    }
+
+    let chain = config
+        .model_chain()
+        .map(|spec| build(spec, config))
+        .collect::<ProviderResult<Vec<_>>>()?;
+    Ok(Box::new(FallbackProvider::new(chain)))
+}
+
+/// Builds the provider for `spec`, taking credentials and endpoints from `config`.
+pub fn build(spec: &ModelSpec, config: &Config) -> ProviderResult<Box<dyn LlmProvider>> {
+    let api_key = config
+        .get_api_key(spec.provider)
+        .map_err(|e| ProviderError::Config(e.to_string()))?;
+    let model = spec.model_id.as_str();
+
+    let provider: Box<dyn LlmProvider> = match spec.provider {
+        AiProvider::OpenAi => Box::new(OpenAiProvider::new(http_client(config)?, api_key, model)),
+        AiProvider::OpenAiCompatible => {
+            let base_url = config
+                .ai_base_url
+                .as_deref()
+                .ok_or_else(|| ProviderError::Config("AI_BASE_URL not configured".to_string()))?;
+            Box::new(OpenAiProvider::compatible(http_client(config)?, api_key, model, base_url))
+        }
+        AiProvider::Anthropic => {
+            let api_key = api_key.ok_or_else(|| ProviderError::Config("Anthropic API key not configured".to_string()))?;
+            Box::new(AnthropicProvider::new(http_client(config)?, api_key, model))
+        }
+        AiProvider::Mock => return Ok(Box::new(MockProvider::new(model, config.mock_seed))),
+    };
+
+    Ok(Box::new(RetryingProvider::new(provider, config.retry.clone())))
+}
+
+fn http_client(config: &Config) -> ProviderResult<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(config.retry.request_timeout)
+        .build()
+        .map_err(|e| ProviderError::Config(e.to_string()))
+}
+
+/// Sends a request and decodes a successful JSON answer, classifying every
+/// failure into a [`ProviderError`].
+async fn send_json<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> ProviderResult<T> {
+    let response = send(request).await?;
+    Ok(response.json::<T>().await?)
+}
+
+/// Sends a request answered with server-sent events and calls `on_event` with
+/// the event name (empty if unnamed) and data of every event.
+async fn send_sse<F>(request: reqwest::RequestBuilder, mut on_event: F) -> ProviderResult<()>
+where
+    F: FnMut(&str, &str) -> ProviderResult<()> + Send,
+{
+    let mut response = send(request).await?;
+    let mut buffer = Vec::new();
+    let mut event = String::new();
+    let mut data = String::new();
+
+    loop {
+        let chunk = response.chunk().await?;
+        let finished = chunk.is_none();
+        match chunk {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            // Treat a missing trailing newline like a complete last line
+            None => buffer.push(b'\n'),
+        }
+
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                if !data.is_empty() {
+                    on_event(&event, &data)?;
+                }
+                event.clear();
+                data.clear();
+            } else if let Some(name) = line.strip_prefix("event:") {
+                event = name.trim().to_string();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                if !data.is_empty() {
+                    data.push('\n');
+                }
+                data.push_str(value.strip_prefix(' ').unwrap_or(value));
+            }
+        }
+
+        if finished {
+            if !data.is_empty() {
+                on_event(&event, &data)?;
+            }
+            return Ok(());
+        }
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> ProviderResult<reqwest::Response> {
+    let response = request.send().await?;
+    let status = response.status();
+
+    if !status.is_success() {
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        return Err(ProviderError::from_response(status, &headers, body));
+    }
+
+    Ok(response)
+}
+
+fn parse_event<T: DeserializeOwned>(provider: &str, data: &str) -> ProviderResult<T> {
+    serde_json::from_str(data)
+        .map_err(|e| ProviderError::MalformedResponse(format!("invalid {} stream event: {}", provider, e)))
+}
@@ -0,0 +1,150 @@
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use tracing::debug;
+
+use super::{
+    parse_event, send_json, send_sse, Completion, CompletionOptions, LlmProvider, ProviderError, ProviderResult,
+    StreamSink,
+};
+use crate::config::AiProvider;
+use crate::models::{GptChunk, GptCompletion, Message, RequestBody, StreamEvent, StreamOptions, Usage};
+
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Talks to the OpenAI chat completions API or any server implementing it
+/// (Ollama, llama.cpp, vLLM, ...), depending on the base URL.
+pub struct OpenAiProvider {
+    name: &'static str,
+    provider: AiProvider,
+    api_key: Option<String>,
+    model: String,
+    url: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiProvider {
+    pub fn new(client: reqwest::Client, api_key: Option<&str>, model: &str) -> Self {
+        OpenAiProvider::with_base_url("OpenAI", AiProvider::OpenAi, client, api_key, model, DEFAULT_BASE_URL)
+    }
+
+    pub fn compatible(client: reqwest::Client, api_key: Option<&str>, model: &str, base_url: &str) -> Self {
+        OpenAiProvider::with_base_url("OpenAI-compatible", AiProvider::OpenAiCompatible, client, api_key, model, base_url)
+    }
+
+    fn with_base_url(
+        name: &'static str,
+        provider: AiProvider,
+        client: reqwest::Client,
+        api_key: Option<&str>,
+        model: &str,
+        base_url: &str,
+    ) -> Self {
+        OpenAiProvider {
+            name,
+            provider,
+            api_key: api_key.map(str::to_string),
+            model: model.to_string(),
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            client,
+        }
+    }
+
+    fn headers(&self) -> ProviderResult<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &self.api_key {
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {}", api_key))?,
+            );
+        }
+        Ok(headers)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion> {
+        let body = RequestBody {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: options.max_tokens,
+            stream: None,
+            stream_options: None,
+        };
+
+        let request = self.client.post(&self.url).headers(self.headers()?).json(&body);
+        let response = send_json::<GptCompletion>(request).await?;
+
+        debug!("Successfully received completion from {}", self.name);
+        let text = response
+            .choices
+            .first()
+            .map(|c| c.message.content.clone())
+            .ok_or_else(|| ProviderError::MalformedResponse(format!("{} returned no choices", self.name)))?;
+        Ok(Completion {
+            text,
+            provider: self.provider.as_str().to_string(),
+            model: response.model,
+            usage: response
+                .usage
+                .map(|u| Usage {
+                    prompt_tokens: u.prompt_tokens,
+                    completion_tokens: u.completion_tokens,
+                })
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn stream(&self, messages: &[Message], options: &CompletionOptions, sink: &StreamSink) -> ProviderResult<Completion> {
+        let body = RequestBody {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: options.max_tokens,
+            stream: Some(true),
+            stream_options: Some(StreamOptions { include_usage: true }),
+        };
+
+        let mut text = String::new();
+        let mut model = self.model.clone();
+        let mut usage = Usage::default();
+
+        let request = self.client.post(&self.url).headers(self.headers()?).json(&body);
+        send_sse(request, |_, data| {
+            if data.trim() == "[DONE]" {
+                return Ok(());
+            }
+
+            let chunk = parse_event::<GptChunk>(self.name, data)?;
+            if !chunk.model.is_empty() {
+                model = chunk.model;
+            }
+            if let Some(u) = chunk.usage {
+                usage = Usage {
+                    prompt_tokens: u.prompt_tokens,
+                    completion_tokens: u.completion_tokens,
+                };
+            }
+            for delta in chunk.choices.into_iter().filter_map(|choice| choice.delta.content) {
+                text.push_str(&delta);
+                sink(StreamEvent::Delta(delta));
+            }
+            Ok(())
+        })
+        .await?;
+
+        debug!("Successfully streamed completion from {}", self.name);
+        if text.is_empty() {
+            return Err(ProviderError::MalformedResponse(format!("{} returned no content", self.name)));
+        }
+        Ok(Completion {
+            text,
+            provider: self.provider.as_str().to_string(),
+            model,
+            usage,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use async_trait::async_trait;
+use std::time::Duration;
+use tracing::warn;
+
+use super::{Completion, CompletionOptions, LlmProvider, ProviderError, ProviderResult, StreamSink};
+use crate::config::RetryPolicy;
+use crate::models::{Message, StreamEvent};
+
+/// Wraps a provider and retries transient failures with exponential backoff.
+///
+/// A `retry-after` hint from the provider replaces the computed delay. If the
+/// provider asks us to wait longer than `max_delay`, we give up right away
+/// instead of holding on to the request.
+pub struct RetryingProvider {
+    inner: Box<dyn LlmProvider>,
+    policy: RetryPolicy,
+}
+
+impl RetryingProvider {
+    pub fn new(inner: Box<dyn LlmProvider>, policy: RetryPolicy) -> Self {
+        RetryingProvider { inner, policy }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt);
+        self.policy.base_delay.saturating_mul(factor).min(self.policy.max_delay)
+    }
+
+    /// Decides whether `err` after `attempt` retries is worth another try and
+    /// if so, waits for it. Returns the error if we should give up.
+    async fn wait_for_retry(&self, err: ProviderError, attempt: u32) -> Result<(), ProviderError> {
+        if !err.is_retryable() || attempt >= self.policy.max_retries {
+            return Err(err);
+        }
+
+        let delay = match err.retry_after() {
+            Some(delay) if delay > self.policy.max_delay => return Err(err),
+            Some(delay) => delay,
+            None => self.backoff(attempt),
+        };
+
+        warn!(
+            "{} request failed ({}), retry {}/{} in {:?}",
+            self.inner.name(),
+            err,
+            attempt + 1,
+            self.policy.max_retries,
+            delay
+        );
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RetryingProvider {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn complete(&self, messages: &[Message], options: &CompletionOptions) -> ProviderResult<Completion> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.complete(messages, options).await {
+                Ok(completion) => return Ok(completion),
+                Err(err) => self.wait_for_retry(err, attempt).await?,
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn stream(&self, messages: &[Message], options: &CompletionOptions, sink: &StreamSink) -> ProviderResult<Completion> {
+        let mut attempt = 0;
+        loop {
+            if attempt > 0 {
+                sink(StreamEvent::Restart);
+            }
+            match self.inner.stream(messages, options, sink).await {
+                Ok(completion) => return Ok(completion),
+                Err(err) => self.wait_for_retry(err, attempt).await?,
+            }
+            attempt += 1;
+        }
+    }
+}
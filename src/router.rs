@@ -0,0 +1,137 @@
+use crate::feed::FeedFormat;
+
+/// Paths below this prefix belong to the system, never to articles, so new
+/// endpoints cannot collide with a slug.
+pub const SYSTEM_PREFIX: &str = "/_/";
+
+/// Everything the server answers, resolved from the request path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    Robots,
+    Favicon,
+    /// `None` for `/sitemap.xml`, else the page of a split sitemap
+    Sitemap(Option<u32>),
+    Feed(FeedFormat),
+    Admin,
+    Archive,
+    Search,
+    Stream(String),
+    Health,
+    /// Permanently moved, to this path
+    Redirect(&'static str),
+    ArticleList,
+    Article(String),
+    NotFound,
+}
+
+/// Where endpoints used to be, before they moved below [`SYSTEM_PREFIX`].
+const MOVED: &[(&str, &str)] = &[("feed.xml", "/_/feed.xml"), ("atom.xml", "/_/atom.xml")];
+
+pub fn route(path: &str) -> Route {
+    if let Some(system) = path.strip_prefix(SYSTEM_PREFIX) {
+        return system_route(system);
+    }
+
+    let path = path.trim_start_matches('/').trim();
+
+    match path {
+        "robots.txt" => return Route::Robots,
+        "favicon.ico" => return Route::Favicon,
+        "sitemap.xml" => return Route::Sitemap(None),
+        _ => {}
+    }
+
+    if let Some(page) = path
+        .strip_prefix("sitemap-")
+        .and_then(|rest| rest.strip_suffix(".xml"))
+        .and_then(|page| page.parse::<u32>().ok())
+    {
+        return Route::Sitemap(Some(page));
+    }
+
+    if let Some((_, to)) = MOVED.iter().find(|(from, _)| *from == path) {
+        return Route::Redirect(to);
+    }
+
+    // The whole underscore namespace is reserved, not just the prefix
+    if path.starts_with('_') {
+        return Route::NotFound;
+    }
+
+    let slug = slugify_route(path);
+    if slug.is_empty() {
+        Route::ArticleList
+    } else {
+        Route::Article(slug)
+    }
+}
+
+/// The system endpoints, `path` is what follows [`SYSTEM_PREFIX`].
+fn system_route(path: &str) -> Route {
+    match path {
+        "admin" => Route::Admin,
+        "archive" => Route::Archive,
+        "search" => Route::Search,
+        "feed.xml" => Route::Feed(FeedFormat::Rss),
+        "atom.xml" => Route::Feed(FeedFormat::Atom),
+        "health" => Route::Health,
+        _ => match path.strip_prefix("stream/") {
+            Some(slug) => Route::Stream(slugify_route(slug)),
+            None => Route::NotFound,
+        },
+    }
+}
+
+fn slugify_route(route: &str) -> String {
+    route
+        .replace(".", "-")
+        .replace("_", "-")
+        .replace("/", "-")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_system_paths() {
+        assert_eq!(route("/_/admin"), Route::Admin);
+        assert_eq!(route("/_/archive"), Route::Archive);
+        assert_eq!(route("/_/search"), Route::Search);
+        assert_eq!(route("/_/feed.xml"), Route::Feed(FeedFormat::Rss));
+        assert_eq!(route("/_/atom.xml"), Route::Feed(FeedFormat::Atom));
+        assert_eq!(route("/_/health"), Route::Health);
+        assert_eq!(route("/_/stream/Some_Slug"), Route::Stream("some-slug".to_string()));
+        assert_eq!(route("/_/unknown"), Route::NotFound);
+    }
+
+    #[test]
+    fn routes_root_files() {
+        assert_eq!(route("/robots.txt"), Route::Robots);
+        assert_eq!(route("/favicon.ico"), Route::Favicon);
+        assert_eq!(route("/sitemap.xml"), Route::Sitemap(None));
+        assert_eq!(route("/sitemap-3.xml"), Route::Sitemap(Some(3)));
+        assert_eq!(route("/sitemap-x.xml"), Route::Article("sitemap-x-xml".to_string()));
+    }
+
+    #[test]
+    fn redirects_moved_feeds() {
+        assert_eq!(route("/feed.xml"), Route::Redirect("/_/feed.xml"));
+        assert_eq!(route("/atom.xml"), Route::Redirect("/_/atom.xml"));
+    }
+
+    #[test]
+    fn reserves_the_underscore_namespace() {
+        for path in ["/_admin", "/_archive", "/_search", "/_", "/_/", "/__x"] {
+            assert_eq!(route(path), Route::NotFound, "{}", path);
+        }
+    }
+
+    #[test]
+    fn routes_articles_by_slug() {
+        assert_eq!(route("/"), Route::ArticleList);
+        assert_eq!(route("/Hello_World.Again/Part"), Route::Article("hello-world-again-part".to_string()));
+        assert_eq!(route("/caf%C3%A9"), Route::Article("caf%c3%a9".to_string()));
+    }
+}
@@ -1,319 +1,798 @@
-use bytes::Bytes;
 use comrak::{
     format_html_with_plugins, parse_document, plugins, Arena, ComrakOptions, ComrakPlugins,
     ComrakRenderOptions,
 };
-use http_body_util::Full;
-use hyper::{Request, Response};
+use hyper::header::HeaderMap;
+use hyper::{Method, Request, Response, StatusCode};
+use minijinja::{context, Value};
 use std::convert::Infallible;
+use std::net::SocketAddr;
 use tracing::{debug, error, info, warn};
 
-use crate::ai::{capitalize_words, fetch_content, fetch_title, unslugify};
-use crate::config::Config;
+use crate::body::{full, Body};
+use crate::budget::check_budget;
+use crate::config::{ClientRateLimit, Config, RateLimitPolicy};
 use crate::database::{
-    calculate_wait_time, check_daily_rate_limit, check_generation_lock, create_generation_lock,
-    get_article_by_slug, get_pool, get_recent_articles, insert_article,
+    calculate_wait_time, check_connection, check_rate_limit, create_job, get_article_by_slug, get_article_index,
+    get_articles_page, get_job, get_monthly_usage, get_pool, get_recent_generations, is_generation_locked,
+    search_articles, set_article_indexable,
 };
+use crate::feed::handle_feed;
+use crate::jobs;
+use crate::live;
+use crate::models::{JobStatus, SearchResult, MATCH_END, MATCH_START};
+use crate::router::{route, Route};
+use crate::sitemap::handle_sitemap;
+use crate::stream::handle_stream;
+use crate::theme;
+use crate::throttle::register_attempt;
+
+/// Articles per page of the article list.
+const ARTICLES_PER_PAGE: u32 = 20;
+
+/// Maximum number of articles listed for a search.
+const SEARCH_RESULTS: u32 = 50;
+
+/// How long a failed generation blocks new attempts for the same slug.
+const FAILED_JOB_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+pub async fn handle_request(req: Request<hyper::body::Incoming>, remote_addr: SocketAddr, config: Config) -> Result<Response<Body>, Infallible> {
+    // Every page is only ever read, other methods have no meaning here
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return Ok(method_not_allowed());
+    }
+
+    let query = req.uri().query();
+    let headers = req.headers();
+
+    match route(req.uri().path()) {
+        Route::Robots => Ok(handle_robots(headers, &config)),
+        Route::Favicon => Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(full(""))
+            .unwrap_or_else(|_| Response::new(full("")))),
+        Route::Sitemap(page) => Ok(handle_sitemap(page, headers, &config)),
+        Route::Feed(format) => Ok(handle_feed(format, headers, &config)),
+        Route::Admin => handle_admin(query, &config).await,
+        Route::Archive => handle_archive(&config).await,
+        Route::Search => handle_search(query).await,
+        Route::Stream(slug) => Ok(handle_stream(&slug)),
+        Route::Health => Ok(handle_health()),
+        Route::Redirect(to) => Ok(moved_permanently(to, query)),
+        Route::ArticleList => handle_article_list(query, &config).await,
+        Route::Article(slug) => {
+            info!("Processing request for slug: {}", slug);
+            let client = client_key(headers, remote_addr, &config.client_rate_limit);
+            handle_article_request(&slug, &client, &config).await
+        }
+        Route::NotFound => Ok(not_found()),
+    }
+}
+
+fn handle_health() -> Response<Body> {
+    let (status, body) = match check_connection(get_pool()) {
+        Ok(()) => (StatusCode::OK, "ok\n"),
+        Err(e) => {
+            error!("Health check failed: {:?}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, "database unavailable\n")
+        }
+    };
 
-pub async fn handle_request(req: Request<hyper::body::Incoming>, config: Config) -> Result<Response<Full<Bytes>>, Infallible> {
-    let uri = req.uri().path();
-    let route = uri.trim_start_matches('/').trim();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .body(full(body))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
+
+fn moved_permanently(to: &str, query: Option<&str>) -> Response<Body> {
+    let location = match query {
+        Some(query) => format!("{}?{}", to, query),
+        None => to.to_string(),
+    };
 
-    if route == "robots.txt" {
-        return Ok(Response::new(Full::new(Bytes::from(
-            "User-agent: *\nDisallow: /",
-        ))));
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header("Location", location)
+        .body(full(""))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
+
+fn method_not_allowed() -> Response<Body> {
+    let mut response = html_response(
+        StatusCode::METHOD_NOT_ALLOWED,
+        error_page("Method not allowed", "Pages can only be requested with GET or HEAD."),
+    );
+    response
+        .headers_mut()
+        .insert("Allow", hyper::header::HeaderValue::from_static("GET, HEAD"));
+    response
+}
+
+/// The public URL of the site, for absolute links.
+pub fn base_url(headers: &HeaderMap, config: &Config) -> String {
+    if let Some(url) = &config.base_url {
+        return url.clone();
     }
 
-    if route == "favicon.ico" {
-        return Ok(Response::new(Full::new(Bytes::from(""))));
+    match headers.get("host").and_then(|v| v.to_str().ok()) {
+        Some(host) => format!("http://{}", host),
+        None => format!("http://localhost:{}", config.server_port),
     }
+}
 
-    let slug = route
-        .replace(".", "-")
-        .replace("_", "-")
-        .replace("/", "-")
-        .to_lowercase();
+fn handle_robots(headers: &HeaderMap, config: &Config) -> Response<Body> {
+    let robots = if let Some(template) = &config.robots_txt {
+        let base_url = base_url(headers, config);
+        template
+            .replace("{sitemap_url}", &format!("{}/sitemap.xml", base_url))
+            .replace("{base_url}", &base_url)
+    } else if !config.indexing_enabled {
+        "User-agent: *\nDisallow: /".to_string()
+    } else {
+        // Everything below /_ is generated on request, not worth crawling
+        format!("User-agent: *\nDisallow: /_\n\nSitemap: {}/sitemap.xml\n", base_url(headers, config))
+    };
 
-    info!("Processing request for slug: {}", slug);
+    Response::builder()
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(full(robots))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
 
-    if slug.is_empty() {
-        return handle_article_list().await;
+/// Identifies the client for per-client throttling: its IP address, taken from
+/// the configured proxy header if present, optionally with the User-Agent.
+fn client_key(headers: &HeaderMap, remote_addr: SocketAddr, policy: &ClientRateLimit) -> String {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    let ip = policy
+        .ip_header
+        .as_deref()
+        .and_then(header)
+        // Each proxy appends the address it got the request from, the
+        // entries before that are whatever the client sent
+        .and_then(|value| value.rsplit(',').nth(policy.trusted_proxies - 1))
+        .map(|ip| ip.trim().to_string())
+        .filter(|ip| !ip.is_empty())
+        .unwrap_or_else(|| remote_addr.ip().to_string());
+
+    if policy.by_user_agent {
+        format!("{} {}", ip, header("user-agent").unwrap_or_default())
+    } else {
+        ip
     }
-
-    handle_article_request(&slug, &config).await
 }
 
-async fn handle_article_list() -> Result<Response<Full<Bytes>>, Infallible> {
+async fn handle_article_list(query: Option<&str>, config: &Config) -> Result<Response<Body>, Infallible> {
     let pool = get_pool();
+    let before = query_param(query, "before");
 
-    let articles = match get_recent_articles(pool) {
+    // One more than shown tells whether there is a next page
+    let mut articles = match get_articles_page(pool, before.as_deref(), ARTICLES_PER_PAGE + 1) {
         Ok(articles) => {
             debug!("Successfully fetched {} recent articles", articles.len());
             articles
         },
         Err(e) => {
             error!("Failed to fetch articles: {:?}", e);
-            return Ok(Response::new(Full::new(Bytes::from(
-                "Failed to fetch articles"
-            ))));
+            return Ok(server_error("Failed to fetch articles."));
         }
     };
 
-    let mut html = String::new();
-    html.push_str("<ul class='article-list'>");
-    
-    for (title, slug) in articles {
-        let cleaned_title = strip_title_hash(&title.trim_matches('"'));
-        html.push_str(&format!(
-            "<li><a href=\"{}\">{}</a></li>",
-            slug,
-            cleaned_title
-        ));
-    }
-    
-    html.push_str("</ul>");
-    let html = apply_layout("Blog", &html);
+    let older = articles.len() > ARTICLES_PER_PAGE as usize;
+    articles.truncate(ARTICLES_PER_PAGE as usize);
 
-    Ok(Response::new(Full::new(Bytes::from(html))))
+    let older_url = articles
+        .last()
+        .filter(|_| older)
+        .map(|(_, last)| older_url(last));
+    let newer_url = before.as_ref().map(|_| "/");
+    let articles: Vec<Value> = articles
+        .iter()
+        .map(|(title, slug)| context! { slug, title => strip_title_hash(title.trim_matches('"')) })
+        .collect();
+
+    let canonical_url = config.base_url.as_ref().filter(|_| before.is_none()).map(|url| format!("{}/", url));
+
+    let robots = listing_robots(config);
+    let html = theme::render(
+        "list.html",
+        context! { title => "Blog", robots => robots.directives(), canonical_url, articles, newer_url, older_url },
+    );
+
+    Ok(page_response(html, robots, config))
 }
 
-async fn handle_article_request(slug: &str, config: &Config) -> Result<Response<Full<Bytes>>, Infallible> {
+/// Link to the page of articles older than `last`, `query_param` decodes it.
+fn older_url(last: &str) -> String {
+    format!("/?before={}", percent_encode(last))
+}
+
+async fn handle_article_request(slug: &str, client: &str, config: &Config) -> Result<Response<Body>, Infallible> {
     let pool = get_pool();
 
     let existing_article = get_article_by_slug(pool, slug);
 
-    if let Ok(content) = existing_article {
+    if let Ok(article) = existing_article {
         info!("Found existing article for slug: {}", slug);
-        let raw = if content.content.trim().starts_with("#") {
-            remove_first_line(&content.content)
+        let raw = if article.content.trim().starts_with("#") {
+            remove_first_line(&article.content)
         } else {
-            content.content
+            article.content
         };
         let html = markdown_parse(&raw);
-        let cleaned_title = strip_title_hash(&content.title.trim_matches('"'));
-        let html = apply_layout(&cleaned_title, &html);
-        return Ok(Response::new(Full::new(Bytes::from(html))));
+        let cleaned_title = strip_title_hash(article.title.trim_matches('"'));
+        let robots = if config.indexing_enabled && article.indexable {
+            Robots::Index
+        } else {
+            Robots::Hidden
+        };
+        let canonical_url = config.base_url.as_ref().map(|url| format!("{}/{}", url, slug));
+        let html = theme::render(
+            "article.html",
+            context! { title => cleaned_title, robots => robots.directives(), canonical_url, content => html, slug },
+        );
+        return Ok(page_response(html, robots, config));
+    }
+
+    // Follow the generation already running in this process
+    if live::is_live(slug) {
+        return Ok(being_written(slug));
+    }
+
+    if let Some(response) = job_status_page(slug) {
+        return Ok(response);
+    }
+
+    if let Some(wait) = register_attempt(client, &config.client_rate_limit) {
+        warn!("Client {} exceeded its generation attempts, refusing slug: {}", client, slug);
+        let msg = "Too many new articles requested. Please try again later.";
+        let html = try_later_page("Too many requests", msg);
+        let wait = chrono::Duration::from_std(wait).unwrap_or_default();
+        return Ok(retry_later_response(StatusCode::TOO_MANY_REQUESTS, html, wait));
+    }
+
+    if let Some((wait, limit, window)) = rate_limit_wait(slug, config) {
+        let msg = format!(
+            "Only {} can be generated per {}. Please wait {} before generating a new article.",
+            plural(limit as i64, "article"),
+            window_name(window, &config.rate_limit),
+            format_wait_time(wait)
+        );
+        let html = try_later_page("Try later", &msg);
+        return Ok(retry_later_response(StatusCode::TOO_MANY_REQUESTS, html, wait));
+    }
+
+    match check_budget(pool, &config.budget) {
+        Ok(Some(exceeded)) => {
+            warn!("{} budget of {} reached, refusing to generate slug: {}", exceeded.period, exceeded.limit, slug);
+            let msg = format!(
+                "The {} budget of {} for generating articles has been used up. New articles can be generated once the budget resets.",
+                exceeded.period, exceeded.limit
+            );
+            let html = try_later_page("Budget reached", &msg);
+            return Ok(retry_later_response(StatusCode::SERVICE_UNAVAILABLE, html, exceeded.resets_in));
+        }
+        Ok(None) => {}
+        Err(e) => {
+            // Without usage data we can't tell whether generating is affordable
+            error!("Failed to check budget: {:?}", e);
+            return Ok(unavailable());
+        }
     }
 
-    if let Ok(Some(last_date)) = check_daily_rate_limit(pool) {
-        if let Ok(hours_to_wait) = calculate_wait_time(&last_date) {
-            warn!("Rate limit exceeded for slug: {}, {} hours remaining", slug, hours_to_wait);
+    let lock = match jobs::try_lock(slug, config) {
+        Ok(Some(lock)) => lock,
+        // Someone else got there first, wait for their result
+        Ok(None) => return Ok(being_written(slug)),
+        Err(e) => {
+            error!("Failed to lock slug '{}' for generation: {:?}", slug, e);
+            return Ok(unavailable());
+        }
+    };
+
+    if let Err(e) = create_job(pool, slug) {
+        error!("Failed to create generation job for slug '{}': {:?}", slug, e);
+        return Ok(unavailable());
+    }
+
+    jobs::start(lock, config.clone());
+
+    Ok(being_written(slug))
+}
+
+/// Answer for jobs that have not finished yet: an article in the making
+/// or a recent failure. `None` means a new generation may be attempted.
+fn job_status_page(slug: &str) -> Option<Response<Body>> {
+    let job = match get_job(get_pool(), slug) {
+        Ok(Some(job)) => job,
+        Ok(None) => return None,
+        Err(e) => {
+            error!("Failed to fetch job for slug '{}': {:?}", slug, e);
+            return None;
+        }
+    };
+
+    match job.status {
+        JobStatus::Pending | JobStatus::Running => match is_generation_locked(get_pool(), slug) {
+            Ok(false) => {
+                // Its process died without finishing, start over
+                warn!("Job for slug '{}' is {} without a lock, restarting it", slug, job.status.as_str());
+                None
+            }
+            Ok(true) => Some(being_written(slug)),
+            Err(e) => {
+                error!("Failed to check generation lock for slug '{}': {:?}", slug, e);
+                Some(being_written(slug))
+            }
+        },
+        JobStatus::Failed => {
+            // Don't restart right away, the page may be reloaded automatically
+            let cooldown = calculate_wait_time(&job.updated_at, FAILED_JOB_COOLDOWN).ok()?;
+            if cooldown <= chrono::Duration::zero() {
+                return None;
+            }
+            debug!("Job for slug '{}' failed recently: {:?}", slug, job.error);
             let msg = format!(
-                "Only one article can be generated per day. Please wait {} hours before generating a new article.",
-                hours_to_wait
+                "Writing this article failed. Please try again in {}.",
+                format_wait_time(cooldown)
             );
-            let html = apply_layout("Try later", &msg);
-            return Ok(Response::new(Full::new(Bytes::from(html))));
+            let html = try_later_page("Try later", &msg);
+            Some(retry_later_response(StatusCode::SERVICE_UNAVAILABLE, html, cooldown))
         }
+        // The article is gone again, or storing it failed; let it be regenerated
+        JobStatus::Done => None,
     }
+}
 
-    if let Ok(true) = check_generation_lock(pool) {
-        warn!("Generation locked for slug: {}", slug);
-        let msg = "Content creation temporary locked".to_string();
-        let html = apply_layout("Try later", &msg);
-        return Ok(Response::new(Full::new(Bytes::from(html))));
+fn being_written(slug: &str) -> Response<Body> {
+    debug!("Article for slug '{}' is being written", slug);
+    let msg = r#"<div id="live"><p>This article is being written right now. The page reloads automatically once it is done.</p></div>
+    <script>
+        (function () {
+            var reload = function (delay) { setTimeout(function () { location.reload(); }, delay); };
+            var events = new EventSource("/_/stream" + location.pathname);
+            var done = function () { events.close(); reload(0); };
+            events.addEventListener("title", function (e) {
+                document.title = e.data;
+                document.querySelector("article h1").textContent = e.data;
+            });
+            events.addEventListener("update", function (e) {
+                document.getElementById("live").innerHTML = e.data;
+            });
+            events.addEventListener("done", done);
+            events.addEventListener("failed", done);
+            events.addEventListener("pending", function () { events.close(); reload(5000); });
+            events.onerror = function () { events.close(); reload(5000); };
+        })();
+    </script>"#;
+    let head = r#"<noscript><meta http-equiv="refresh" content="5"></noscript>"#;
+    let html = render_layout("Being written", Robots::Hidden, head, msg);
+    // Not there yet, crawlers and monitoring should come back later, too
+    retry_later_response(StatusCode::SERVICE_UNAVAILABLE, html, chrono::Duration::seconds(5))
+}
+
+async fn handle_archive(config: &Config) -> Result<Response<Body>, Infallible> {
+    let articles = match get_article_index(get_pool()) {
+        Ok(articles) => articles,
+        Err(e) => {
+            error!("Failed to fetch article index: {:?}", e);
+            return Ok(server_error("Failed to fetch articles."));
+        }
+    };
+
+    // Grouped by month, newest first as they come
+    let mut months: Vec<(String, Vec<Value>)> = Vec::new();
+    for article in articles {
+        let month = chrono::NaiveDateTime::parse_from_str(&article.created_at, "%Y-%m-%d %H:%M:%S")
+            .map(|date| date.format("%B %Y").to_string())
+            .unwrap_or_else(|_| "Undated".to_string());
+
+        let entry = context! {
+            slug => article.slug,
+            title => strip_title_hash(article.title.trim_matches('"')),
+        };
+        match months.last_mut() {
+            Some((current, articles)) if *current == month => articles.push(entry),
+            _ => months.push((month, vec![entry])),
+        }
     }
+    let months: Vec<Value> = months
+        .into_iter()
+        .map(|(name, articles)| context! { name, articles })
+        .collect();
+
+    let robots = listing_robots(config);
+    let html = theme::render("archive.html", context! { title => "Archive", robots => robots.directives(), months });
+    Ok(page_response(html, robots, config))
+}
 
-    let _ = create_generation_lock(pool);
-    info!("Starting article generation for slug: {}", slug);
+async fn handle_search(query: Option<&str>) -> Result<Response<Body>, Infallible> {
+    let q = query_param(query, "q").unwrap_or_default();
+    let json = query_param(query, "format").as_deref() == Some("json");
 
-    let title = fetch_title(slug, config).await.unwrap_or_else(|e| {
-        warn!("Failed to fetch title for slug '{}': {:?}", slug, e);
-        let t = unslugify(slug);
-        capitalize_words(&t)
-    });
-    let title = title.trim_matches('"');
-    debug!("Generated title: {}", title);
+    let results = match search_articles(get_pool(), &q, SEARCH_RESULTS) {
+        Ok(results) => results,
+        Err(e) => {
+            error!("Failed to search articles for '{}': {:?}", q, e);
+            return Ok(server_error("Failed to search articles."));
+        }
+    };
+    debug!("Search for '{}' found {} articles", q, results.len());
+
+    // Both variants carry the same HTML-escaped, <mark>-highlighted text
+    let results: Vec<SearchResult> = results
+        .into_iter()
+        .map(|result| SearchResult {
+            title: highlight(&strip_title_hash(result.title.trim_matches('"'))),
+            snippet: highlight(&plain_snippet(&result.snippet)),
+            slug: result.slug,
+        })
+        .collect();
+
+    if json {
+        let body = serde_json::json!({ "query": q, "results": results }).to_string();
+        let response = Response::builder()
+            .header("Content-Type", "application/json")
+            .body(full(body))
+            .unwrap_or_else(|_| Response::new(full("[]")));
+        return Ok(response);
+    }
 
-    let content = fetch_content(title, config).await.unwrap_or_else(|e| {
-        error!("Failed to fetch content for title '{}': {:?}", title, e);
-        crate::models::Content {
-            title: "".to_string(),
-            content: "".to_string(),
+    let results: Vec<Value> = results
+        .into_iter()
+        .map(|result| context! { slug => result.slug, title => result.title, snippet => result.snippet })
+        .collect();
+    let html = theme::render(
+        "search.html",
+        context! { title => "Search", robots => Robots::Hidden.directives(), q, results },
+    );
+    Ok(html_response(StatusCode::OK, html))
+}
+
+/// Escapes `s` for HTML and turns the match markers of a search result into
+/// `<mark>` elements.
+pub fn highlight(s: &str) -> String {
+    escape_html(s)
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+/// Drops the markdown syntax from a snippet of an article: headings,
+/// emphasis and the targets of links.
+fn plain_snippet(s: &str) -> String {
+    let mut plain = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '#' | '*' | '[' => {}
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            ']' => {}
+            '\n' => plain.push(' '),
+            c => plain.push(c),
         }
-    });
+    }
 
-    if content.content.is_empty() {
-        error!("No content generated for slug: {}", slug);
-        return Ok(Response::new(Full::new(Bytes::from(
-            "No content found for this article",
-        ))));
+    plain.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+async fn handle_admin(query: Option<&str>, config: &Config) -> Result<Response<Body>, Infallible> {
+    let authorized = match (&config.admin_token, query_param(query, "token")) {
+        (Some(expected), Some(given)) => *expected == given,
+        _ => false,
+    };
+
+    if !authorized {
+        warn!("Unauthorized admin request");
+        return Ok(not_found());
     }
 
-    match insert_article(pool, slug, &content.title, &content.content) {
-        Ok(_) => info!("Successfully stored article for slug: {}", slug),
-        Err(e) => error!("Failed to store article for slug '{}': {:?}", slug, e),
+    let pool = get_pool();
+    let mut notice = None;
+
+    if let (Some(slug), Some(value)) = (query_param(query, "index"), query_param(query, "value")) {
+        let indexable = value == "1";
+        notice = match set_article_indexable(pool, &slug, indexable) {
+            Ok(true) => {
+                info!("Article '{}' marked as {}indexable", slug, if indexable { "" } else { "not " });
+                Some(format!("{} may {}be indexed now.", slug, if indexable { "" } else { "not " }))
+            }
+            Ok(false) => Some(format!("There is no article {}.", slug)),
+            Err(e) => {
+                error!("Failed to update indexing of article '{}': {:?}", slug, e);
+                Some("Failed to update the article.".to_string())
+            }
+        };
     }
 
-    let raw = if content.content.trim().starts_with("#") {
-        remove_first_line(&content.content)
-    } else {
-        content.content
+    let (months, generations) = match (get_monthly_usage(pool), get_recent_generations(pool, 50)) {
+        (Ok(months), Ok(generations)) => (months, generations),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Failed to fetch generation metadata: {:?}", e);
+            return Ok(server_error("Failed to fetch generation metadata."));
+        }
     };
-    let html = markdown_parse(&raw);
-    let cleaned_title = strip_title_hash(&content.title.trim_matches('"'));
-    let html = apply_layout(&cleaned_title, &html);
 
-    Ok(Response::new(Full::new(Bytes::from(html))))
+    let months: Vec<Value> = months
+        .into_iter()
+        .map(|month| {
+            context! {
+                month => month.month,
+                articles => month.articles,
+                prompt_tokens => month.prompt_tokens,
+                completion_tokens => month.completion_tokens,
+                cost => format!("${:.2}", month.cost_usd),
+            }
+        })
+        .collect();
+
+    let token = percent_encode(config.admin_token.as_deref().unwrap_or_default());
+    let generations: Vec<Value> = generations
+        .into_iter()
+        .map(|generation| {
+            let cost = generation
+                .cost_usd
+                .map(|cost| format!("${:.4}", cost))
+                .unwrap_or_else(|| "n/a".to_string());
+            let toggle_url = generation.indexable.map(|indexable| {
+                format!(
+                    "/_/admin?token={}&index={}&value={}",
+                    token,
+                    percent_encode(&generation.slug),
+                    if indexable { 0 } else { 1 }
+                )
+            });
+            context! {
+                created_at => generation.created_at,
+                slug => generation.slug,
+                provider => generation.provider,
+                model => generation.model,
+                prompt_tokens => generation.prompt_tokens,
+                completion_tokens => generation.completion_tokens,
+                latency => format!("{:.1}s", generation.latency_ms as f64 / 1000.0),
+                cost,
+                indexable => generation.indexable,
+                toggle_url,
+                succeeded => generation.succeeded,
+            }
+        })
+        .collect();
+
+    let html = theme::render(
+        "admin.html",
+        context! { title => "Admin", robots => Robots::Hidden.directives(), notice, months, generations },
+    );
+    Ok(html_response(StatusCode::OK, html))
 }
 
-fn remove_first_line(s: &str) -> String {
+/// Returns the percent-decoded value of the first `name` parameter in `query`.
+fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| percent_decode(value))
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// How long to wait until the rate limit policy allows another article, if
+/// it currently doesn't. Errors are logged and don't block generation.
+/// How long until a new article may be generated, with the limit and window
+/// that block the longest.
+fn rate_limit_wait(slug: &str, config: &Config) -> Option<(chrono::Duration, u32, std::time::Duration)> {
+    let pool = get_pool();
+    let mut wait: Option<(chrono::Duration, u32, std::time::Duration)> = None;
+
+    for (limit, window) in config.rate_limit.limits() {
+        let blocking_date = match check_rate_limit(pool, limit, window) {
+            Ok(Some(date)) => date,
+            Ok(None) => continue,
+            Err(e) => {
+                error!("Failed to check rate limit: {:?}", e);
+                continue;
+            }
+        };
+
+        match calculate_wait_time(&blocking_date, window) {
+            Ok(remaining) => {
+                warn!("Rate limit of {} per {:?} exceeded for slug: {}, {} remaining", limit, window, slug, format_wait_time(remaining));
+                if wait.is_none_or(|(longest, _, _)| remaining > longest) {
+                    wait = Some((remaining, limit, window));
+                }
+            }
+            Err(e) => error!("Failed to calculate wait time from '{}': {}", blocking_date, e),
+        }
+    }
+
+    wait
+}
+
+/// Names a rate limit window: the configured one by its name, the shorter
+/// burst window by its length.
+fn window_name(window: std::time::Duration, policy: &RateLimitPolicy) -> String {
+    if window == policy.window {
+        return policy.window_name.to_string();
+    }
+
+    let minutes = ((window.as_secs() + 30) / 60).max(1) as i64;
+    match (minutes / 60, minutes % 60) {
+        (1, 0) => "hour".to_string(),
+        (hours, 0) => plural(hours, "hour"),
+        _ if minutes == 1 => "minute".to_string(),
+        _ => plural(minutes, "minute"),
+    }
+}
+
+fn format_wait_time(wait: chrono::Duration) -> String {
+    // Round up, "0 minutes" would be a lie
+    let minutes = (wait.num_seconds() + 59) / 60;
+    if minutes < 60 {
+        return plural(minutes.max(1), "minute");
+    }
+    plural((minutes + 59) / 60, "hour")
+}
+
+fn plural(count: i64, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+pub fn remove_first_line(s: &str) -> String {
     s.lines().clone().skip(1).collect::<Vec<&str>>().join("\n")
 }
 
-fn strip_title_hash(title: &str) -> String {
+pub fn strip_title_hash(title: &str) -> String {
     let s = title.trim_start_matches('#').trim_start();
     s.trim_matches('*').to_string()
 }
 
-fn apply_layout(title: &str, content: &str) -> String {
-    format!(
-        r#"
-        <!DOCTYPE html>
-        <html lang="en">
-        <head>
-            <meta charset="utf-8">
-            <meta name="viewport" content="initial-scale=1, width=device-width">
-            <meta name="robots" content="noindex,nofollow">
-            <title>{}</title>
-            <style>
-                pre {{
-                    margin: 0 0 2rem 0;
-                    overflow: auto;
-                    padding: 0.5rem;
-                }}
-
-                :root {{
-                    color-scheme: light dark;  
-                }}
-
-                body {{
-                    display: flex;
-                    flex-direction: column;
-                    min-height: 100dvh;
-                    margin: 0;
-                    padding: 0;
-                    width: 100%;
-                }}
-
-                article {{
-                    padding: 1rem;
-                }}
-
-                article :first-child {{
-                    margin-top: 0;
-                }}
-
-                .article-list {{
-                    list-style: none;
-                    padding: 0;
-                    margin: 0;
-                }}
-
-                .article-list li {{
-                    border-radius: 0.5rem;
-                    border: 1px solid #ccc;
-                    font-family: system-ui, ui-sans-serif,  sans-serif;
-                    margin: 0 0 1rem 0;
-                    padding: 1rem;
-                }}
-
-                .article-list li a {{
-                    text-decoration: none;
-                    font-size: 1.2rem;
-                }}
-
-                @media (prefers-color-scheme: light) {{
-                    header a {{
-                        color: black;
-                    }}
-                }}
-                @media (prefers-color-scheme: dark) {{
-                    header a {{
-                        color: white;
-                    }}
-                }}
-
-                header a {{
-                    text-decoration: none;
-                }}
-
-                p, ol, ul {{
-                    font-family: system-ui, ui-sans-serif,  sans-serif;
-                    hyphens: auto;
-                    line-height: 1.5;
-                    margin: 0 0 2rem 0;
-                    padding: 0;
-                    text-align: justify;
-                }}
-
-                ol, ul {{
-                    padding: 0 0 0 3rem;
-                }}
-
-                header, footer {{
-                    padding: 1rem;
-                    font-family: system-ui, ui-sans-serif, sans-serif;
-                    font-size: 1rem;
-                }}
-                @media (prefers-color-scheme: light) {{
-                    header, footer {{
-                        background-color: #f5f5f5;
-                    }}
-                }}
-                @media (prefers-color-scheme: dark) {{
-                    header, footer {{
-                        background-color: #333;
-                    }}
-                }}
-
-                footer {{
-                    margin-top: auto;
-                }}
-
-                header h1 {{
-                    margin: 0;
-                    padding: 0;
-                }}
-
-                @media screen and (min-width: 768px) {{
-                    header {{
-                        padding: 2.5rem;
-                    }}
-
-                    article {{
-                        max-width: 960px;
-                        margin: 0 auto;
-                        padding: 2.5rem;
-                        width: 100%;
-                    }}
-                }}
-            </style>
-        </head>
-        <body>
-            <header>
-                <h1><a href="/">Autoblogger</a></h1>
-            </header>
-            <article>
-                <h1>{}</h1>
-                {}
-            </article>
-            <footer>
-                All articles are automatically generated by a LLM (Large Language Model like GPT-4, GPT-5 or Claude). It can contain hallucinations, nonsense, factual errors, and other inaccuracies. Do not take it seriously in any way. Contact: Red Ochsenbein &lt;autoblogger@control.raven.ch&gt; <a href="https://github.com/syeo66/autoblogger">Source on Github</a>
-            </footer>
-        </body>
-        </html>
-        "#,
-        title,
-        title,
-        content.trim()
+/// What crawlers may do with a page. Links in articles mostly lead to
+/// articles that don't exist yet, crawlers following them would have them
+/// generated; so only listings, which link to existing articles, are followed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Robots {
+    Index,
+    Follow,
+    Hidden,
+}
+
+impl Robots {
+    fn directives(&self) -> &'static str {
+        match self {
+            Robots::Index => "index,nofollow",
+            Robots::Follow => "noindex,follow",
+            Robots::Hidden => "noindex,nofollow",
+        }
+    }
+}
+
+fn listing_robots(config: &Config) -> Robots {
+    if config.indexing_enabled {
+        Robots::Follow
+    } else {
+        Robots::Hidden
+    }
+}
+
+/// A page with its robots directives repeated as X-Robots-Tag, if configured.
+fn page_response(html: String, robots: Robots, config: &Config) -> Response<Body> {
+    let mut response = html_response(StatusCode::OK, html);
+    if config.x_robots_tag {
+        response
+            .headers_mut()
+            .insert("X-Robots-Tag", hyper::header::HeaderValue::from_static(robots.directives()));
+    }
+    response
+}
+
+fn html_response(status: StatusCode, html: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body(full(html))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
+
+/// Tells the client when to try again, for rate limits and articles that are
+/// not available yet.
+fn retry_later_response(status: StatusCode, html: String, wait: chrono::Duration) -> Response<Body> {
+    let mut response = html_response(status, html);
+    response
+        .headers_mut()
+        .insert("Retry-After", hyper::header::HeaderValue::from(wait.num_seconds().max(1)));
+    response
+}
+
+fn not_found() -> Response<Body> {
+    html_response(StatusCode::NOT_FOUND, error_page("Not found", "This page does not exist."))
+}
+
+fn server_error(message: &str) -> Response<Body> {
+    html_response(StatusCode::INTERNAL_SERVER_ERROR, error_page("Error", message))
+}
+
+fn unavailable() -> Response<Body> {
+    let html = try_later_page("Try later", "Content creation is currently unavailable.");
+    html_response(StatusCode::SERVICE_UNAVAILABLE, html)
+}
+
+/// Renders `content` within the layout; `head` is added to the head element.
+/// Both are inserted as they are, never pass them anything not escaped.
+fn render_layout(title: &str, robots: Robots, head: &str, content: &str) -> String {
+    theme::render(
+        "page.html",
+        context! { title, robots => robots.directives(), head, content },
+    )
+}
+
+fn error_page(title: &str, message: &str) -> String {
+    theme::render(
+        "error.html",
+        context! { title, robots => Robots::Hidden.directives(), message },
+    )
+}
+
+fn try_later_page(title: &str, message: &str) -> String {
+    theme::render(
+        "try_later.html",
+        context! { title, robots => Robots::Hidden.directives(), message },
     )
-    .trim()
-    .into()
 }
 
-fn markdown_parse(s: &str) -> String {
+pub fn markdown_parse(s: &str) -> String {
     let arena = Arena::new();
 
     let comrak_options = ComrakOptions {
@@ -336,4 +815,107 @@ fn markdown_parse(s: &str) -> String {
     format_html_with_plugins(root, &comrak_options, &mut html, &plugins).unwrap();
 
     String::from_utf8(html).unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_html_escapes_markup_and_quotes() {
+        assert_eq!(
+            escape_html("<script>alert(1)</script>\"'&"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;&quot;&#39;&amp;"
+        );
+    }
+
+    #[test]
+    fn highlight_escapes_before_marking() {
+        let snippet = format!("{}<script>{}alert(1)</script>", MATCH_START, MATCH_END);
+        assert_eq!(highlight(&snippet), "<mark>&lt;script&gt;</mark>alert(1)&lt;/script&gt;");
+    }
+
+    fn proxied(trusted_proxies: usize, by_user_agent: bool) -> ClientRateLimit {
+        ClientRateLimit {
+            max_attempts: 3,
+            window: std::time::Duration::from_secs(3600),
+            by_user_agent,
+            ip_header: Some("X-Forwarded-For".to_string()),
+            trusted_proxies,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn client_key_ignores_spoofed_forwarded_entries() {
+        let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let spoofed = headers(&[("x-forwarded-for", "1.1.1.1, 2.2.2.2, 203.0.113.7")]);
+
+        assert_eq!(client_key(&spoofed, peer, &proxied(1, false)), "203.0.113.7");
+        assert_eq!(client_key(&spoofed, peer, &proxied(2, false)), "2.2.2.2");
+        // More proxies configured than entries: nothing trustworthy, fall back to the peer
+        assert_eq!(client_key(&spoofed, peer, &proxied(4, false)), "10.0.0.1");
+    }
+
+    #[test]
+    fn client_key_falls_back_to_the_peer_address() {
+        let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+
+        assert_eq!(client_key(&HeaderMap::new(), peer, &proxied(1, false)), "10.0.0.1");
+        assert_eq!(client_key(&headers(&[("x-forwarded-for", " ")]), peer, &proxied(1, false)), "10.0.0.1");
+
+        let mut direct = proxied(1, false);
+        direct.ip_header = None;
+        assert_eq!(client_key(&headers(&[("x-forwarded-for", "1.1.1.1")]), peer, &direct), "10.0.0.1");
+    }
+
+    #[test]
+    fn client_key_adds_the_user_agent() {
+        let peer: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let request = headers(&[("x-forwarded-for", "203.0.113.7"), ("user-agent", "curl/8")]);
+
+        assert_eq!(client_key(&request, peer, &proxied(1, true)), "203.0.113.7 curl/8");
+    }
+
+    #[test]
+    fn wait_times_round_up() {
+        assert_eq!(format_wait_time(chrono::Duration::zero()), "1 minute");
+        assert_eq!(format_wait_time(chrono::Duration::seconds(60)), "1 minute");
+        assert_eq!(format_wait_time(chrono::Duration::seconds(61)), "2 minutes");
+        assert_eq!(format_wait_time(chrono::Duration::minutes(59)), "59 minutes");
+        assert_eq!(format_wait_time(chrono::Duration::minutes(60)), "1 hour");
+        assert_eq!(format_wait_time(chrono::Duration::minutes(61)), "2 hours");
+    }
+
+    #[test]
+    fn names_the_blocking_window() {
+        let policy = RateLimitPolicy {
+            max_articles: 5,
+            window: std::time::Duration::from_secs(24 * 3600),
+            window_name: "day",
+            burst: Some(2),
+        };
+        let hours = |hours: u64| std::time::Duration::from_secs(hours * 3600);
+
+        assert_eq!(window_name(hours(24), &policy), "day");
+        assert_eq!(window_name(hours(24) / 5, &policy), "288 minutes");
+        assert_eq!(window_name(hours(12), &policy), "12 hours");
+        assert_eq!(window_name(hours(1), &policy), "hour");
+    }
+
+    #[test]
+    fn older_url_round_trips_the_cursor() {
+        for slug in ["caf%c3%a9", "fish-&-chips", "a+b#c"] {
+            let url = older_url(slug);
+            let query = url.strip_prefix("/?").unwrap();
+            assert_eq!(query_param(Some(query), "before").as_deref(), Some(slug), "{}", url);
+        }
+    }
+}
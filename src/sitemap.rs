@@ -0,0 +1,92 @@
+use hyper::header::HeaderMap;
+use hyper::{Response, StatusCode};
+use tracing::error;
+
+use crate::body::{full, Body};
+use crate::config::Config;
+use crate::database::{count_indexable_articles, get_pool, get_sitemap_entries};
+use crate::feed::parse_date;
+use crate::server::{base_url, escape_html};
+
+/// Most URLs a single sitemap may list, as defined by sitemaps.org.
+const SITEMAP_ENTRIES: u32 = 50_000;
+
+/// Serves the indexable articles as `/sitemap.xml`, or page `page` (counted from 1) of a split sitemap
+/// at `/sitemap-<page>.xml`. With more articles than fit one sitemap,
+/// `/sitemap.xml` is a sitemap index pointing to the pages. Like robots.txt,
+/// it only invites crawlers with indexing enabled.
+pub fn handle_sitemap(page: Option<u32>, headers: &HeaderMap, config: &Config) -> Response<Body> {
+    if !config.indexing_enabled {
+        return error_response(StatusCode::NOT_FOUND, "No such sitemap");
+    }
+
+    let pool = get_pool();
+    let base_url = base_url(headers, config);
+
+    let count = match count_indexable_articles(pool) {
+        Ok(count) => count,
+        Err(e) => {
+            error!("Failed to count articles for sitemap: {:?}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate sitemap");
+        }
+    };
+    let pages = count.div_ceil(SITEMAP_ENTRIES);
+
+    let xml = match page {
+        None if pages > 1 => Ok(sitemap_index(&base_url, pages)),
+        None => urlset(&base_url, 0),
+        Some(page) if page >= 1 && page <= pages => urlset(&base_url, (page - 1) * SITEMAP_ENTRIES),
+        Some(_) => return error_response(StatusCode::NOT_FOUND, "No such sitemap"),
+    };
+    let xml = match xml {
+        Ok(xml) => xml,
+        Err(e) => {
+            error!("Failed to fetch sitemap entries: {:?}", e);
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Failed to generate sitemap");
+        }
+    };
+
+    Response::builder()
+        .header("Content-Type", "application/xml; charset=utf-8")
+        .body(full(xml))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
+
+fn sitemap_index(base_url: &str, pages: u32) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<sitemapindex xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for page in 1..=pages {
+        xml.push_str(&format!(
+            "<sitemap><loc>{}/sitemap-{}.xml</loc></sitemap>\n",
+            escape_html(base_url),
+            page
+        ));
+    }
+    xml.push_str("</sitemapindex>\n");
+    xml
+}
+
+fn urlset(base_url: &str, offset: u32) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let entries = get_sitemap_entries(get_pool(), offset, SITEMAP_ENTRIES)?;
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for (slug, modified) in entries {
+        xml.push_str(&format!("<url><loc>{}/{}</loc>", escape_html(base_url), escape_html(&slug)));
+        if let Some(modified) = parse_date(&modified) {
+            xml.push_str(&format!("<lastmod>{}</lastmod>", modified.format("%Y-%m-%dT%H:%M:%SZ")));
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    Ok(xml)
+}
+
+fn error_response(status: StatusCode, message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(full(message))
+        .unwrap_or_else(|_| Response::new(full("")))
+}
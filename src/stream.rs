@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use hyper::Response;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::debug;
+
+use crate::body::{self, Body};
+use crate::database::{get_article_by_slug, get_pool};
+use crate::live::{self, LiveArticle, LiveStatus};
+use crate::server::{markdown_parse, remove_first_line, strip_title_hash};
+
+/// Comment lines keep proxies from closing a quiet connection.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Rendering the article on every token would be wasteful, updates are
+/// sent at most this often.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Server-sent events following the generation of `slug`:
+///
+/// - `title`: the title of the article, once known
+/// - `update`: the article rendered as HTML so far
+/// - `done`: the article is stored and can be loaded
+/// - `failed`: the generation failed
+/// - `pending`: nothing to follow in this process (yet), try again later
+pub fn handle_stream(slug: &str) -> Response<Body> {
+    let (sender, body) = body::channel();
+
+    if get_article_by_slug(get_pool(), slug).is_ok() {
+        let _ = sender.try_send(event("done", ""));
+    } else if let Some(receiver) = live::subscribe(slug) {
+        debug!("Streaming generation of slug: {}", slug);
+        tokio::task::spawn(follow(receiver, sender));
+    } else {
+        let _ = sender.try_send(event("pending", ""));
+    }
+
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        // Ask nginx & co. not to hold the events back
+        .header("X-Accel-Buffering", "no")
+        .body(body)
+        .unwrap_or_else(|_| Response::new(body::full("")))
+}
+
+async fn follow(mut receiver: watch::Receiver<LiveArticle>, sender: mpsc::Sender<Bytes>) {
+    let mut title = None;
+    let mut text = String::new();
+
+    loop {
+        let article = receiver.borrow_and_update().clone();
+
+        if article.title.is_some() && article.title != title {
+            let heading = strip_title_hash(article.title.as_deref().unwrap_or_default().trim_matches('"'));
+            if sender.send(event("title", &heading)).await.is_err() {
+                return;
+            }
+            title = article.title;
+        }
+
+        if text != article.text {
+            if sender.send(event("update", &render(&article.text))).await.is_err() {
+                return;
+            }
+            text = article.text;
+        }
+
+        match article.status {
+            LiveStatus::Writing => {}
+            LiveStatus::Done => {
+                let _ = sender.send(event("done", "")).await;
+                return;
+            }
+            LiveStatus::Failed => {
+                let _ = sender.send(event("failed", "")).await;
+                return;
+            }
+        }
+
+        tokio::time::sleep(UPDATE_INTERVAL).await;
+        loop {
+            match tokio::time::timeout(KEEPALIVE_INTERVAL, receiver.changed()).await {
+                Ok(Ok(())) => break,
+                // The generation went away without a final status
+                Ok(Err(_)) => {
+                    let _ = sender.send(event("failed", "")).await;
+                    return;
+                }
+                Err(_) => {
+                    if sender.send(Bytes::from(":\n\n")).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Renders the text the same way a finished article is displayed.
+fn render(text: &str) -> String {
+    if text.trim().starts_with('#') {
+        markdown_parse(&remove_first_line(text))
+    } else {
+        markdown_parse(text)
+    }
+}
+
+fn event(name: &str, data: &str) -> Bytes {
+    let mut event = format!("event: {}\n", name);
+    for line in data.lines() {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    if data.is_empty() {
+        event.push_str("data:\n");
+    }
+    event.push('\n');
+    Bytes::from(event)
+}
@@ -0,0 +1,189 @@
+use comrak::{markdown_to_html, ComrakOptions};
+use minijinja::{context, Environment, ErrorKind, Value};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tracing::{error, info};
+
+use crate::config::Config;
+
+static THEME: OnceLock<Environment<'static>> = OnceLock::new();
+
+/// Templates every theme provides, built into the binary. A theme directory
+/// only needs to contain the templates it changes.
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("layout.html", include_str!("../templates/layout.html")),
+    ("page.html", include_str!("../templates/page.html")),
+    ("article.html", include_str!("../templates/article.html")),
+    ("list.html", include_str!("../templates/list.html")),
+    ("search_form.html", include_str!("../templates/search_form.html")),
+    ("archive.html", include_str!("../templates/archive.html")),
+    ("search.html", include_str!("../templates/search.html")),
+    ("admin.html", include_str!("../templates/admin.html")),
+    ("error.html", include_str!("../templates/error.html")),
+    ("try_later.html", include_str!("../templates/try_later.html")),
+];
+
+pub fn init(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = &config.theme_dir {
+        info!("Using theme from {}", dir.display());
+    }
+    let env = environment(config.theme_dir.clone(), site(config));
+
+    // Broken templates should stop the server, not fail every request
+    for (name, _) in BUILTIN_TEMPLATES {
+        env.get_template(name)
+            .map_err(|e| format!("Template '{}' is invalid: {:#}", name, e))?;
+    }
+
+    THEME.set(env).map_err(|_| "Theme already initialized")?;
+    Ok(())
+}
+
+fn environment(theme_dir: Option<PathBuf>, site: Value) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_loader(move |name| load(theme_dir.as_deref(), name));
+    env.add_global("site", site);
+    env
+}
+
+/// Renders template `name`. Rendering errors are logged and replaced with a
+/// short notice, a page is better than no response.
+pub fn render(name: &str, context: Value) -> String {
+    let env = THEME.get().expect("Theme not initialized");
+
+    match env.get_template(name).and_then(|template| template.render(context)) {
+        Ok(html) => html,
+        Err(e) => {
+            error!("Failed to render template '{}': {:#}", name, e);
+            "Failed to render page".to_string()
+        }
+    }
+}
+
+/// The site identity, available to every template as `site`.
+fn site(config: &Config) -> Value {
+    let site = &config.site;
+
+    // The footer is written by whoever runs the site, so it may contain HTML
+    let mut options = ComrakOptions::default();
+    options.render.unsafe_ = true;
+
+    context! {
+        name => site.name,
+        description => site.description,
+        author => site.author,
+        contact => site.contact,
+        footer => Value::from_safe_string(markdown_to_html(&site.footer, &options)),
+        language => site.language,
+        url => config.base_url,
+    }
+}
+
+fn load(theme_dir: Option<&Path>, name: &str) -> Result<Option<String>, minijinja::Error> {
+    if let Some(dir) = theme_dir {
+        // Templates can include others by name, keep them inside the theme
+        if name.split('/').any(|part| part == ".." || part.is_empty()) {
+            return Ok(None);
+        }
+
+        let path: PathBuf = dir.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(template) => return Ok(Some(template)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(minijinja::Error::new(
+                    ErrorKind::InvalidOperation,
+                    format!("could not read {}", path.display()),
+                )
+                .with_source(e))
+            }
+        }
+    }
+
+    Ok(BUILTIN_TEMPLATES
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, template)| template.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{highlight, markdown_parse};
+
+    const EVIL: &str = "<script>alert(1)</script>\"";
+
+    fn render_builtin(name: &str, context: Value) -> String {
+        let site = context! { name => "Test", description => "Test", footer => "", language => "en" };
+        environment(None, site)
+            .get_template(name)
+            .and_then(|template| template.render(context))
+            .unwrap()
+    }
+
+    fn assert_escaped(html: &str) {
+        assert!(!html.contains("<script>"), "raw tag in {}", html);
+        assert!(!html.contains("alert(1)</script>"), "raw tag in {}", html);
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;"), "no escaped title in {}", html);
+    }
+
+    #[test]
+    fn list_escapes_titles_and_slugs() {
+        let html = render_builtin(
+            "list.html",
+            context! {
+                title => EVIL,
+                robots => "noindex",
+                articles => vec![context! { slug => EVIL, title => EVIL }, context! { slug => "caf%c3%a9", title => "Café" }],
+            },
+        );
+        assert_escaped(&html);
+        assert!(html.contains("href=\"/&lt;script&gt;alert(1)&lt;&#x2f;script&gt;&quot;\""), "{}", html);
+        // Slugs are request paths, already percent-encoded
+        assert!(html.contains("href=\"/caf%c3%a9\""), "{}", html);
+    }
+
+    #[test]
+    fn footer_leaves_out_a_missing_author() {
+        let site = context! { name => "Test", footer => "", contact => "a@b.c" };
+        let html = environment(None, site)
+            .get_template("error.html")
+            .and_then(|template| template.render(context! { title => "Error", robots => "noindex", message => "" }))
+            .unwrap();
+        assert!(html.contains("<p>Contact: <a href=\"mailto:a@b.c\">a@b.c</a></p>"), "{}", html);
+        assert!(!html.contains("None"), "{}", html);
+    }
+
+    #[test]
+    fn article_escapes_title() {
+        let content = markdown_parse(&format!("{}\n\nText", EVIL));
+        let html = render_builtin(
+            "article.html",
+            context! { title => EVIL, robots => "noindex", content, slug => EVIL, canonical_url => EVIL },
+        );
+        assert_escaped(&html);
+        assert!(html.contains("<h1>&lt;script&gt;alert(1)&lt;&#x2f;script&gt;&quot;</h1>"));
+    }
+
+    #[test]
+    fn archive_escapes_titles_and_slugs() {
+        let articles = vec![context! { slug => EVIL, title => EVIL }];
+        let html = render_builtin(
+            "archive.html",
+            context! { title => "Archive", robots => "noindex", months => vec![context! { name => EVIL, articles }] },
+        );
+        assert_escaped(&html);
+        assert!(html.contains("<h2>&lt;script&gt;"));
+    }
+
+    #[test]
+    fn search_escapes_query_and_results() {
+        let results = vec![context! { slug => EVIL, title => highlight(EVIL), snippet => highlight(EVIL) }];
+        let html = render_builtin(
+            "search.html",
+            context! { title => "Search", robots => "noindex", q => EVIL, results },
+        );
+        assert_escaped(&html);
+        assert!(html.contains("value=\"&lt;script&gt;alert(1)&lt;&#x2f;script&gt;&quot;\""));
+    }
+}
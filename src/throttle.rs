@@ -0,0 +1,107 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::ClientRateLimit;
+
+/// Generation attempts per client key, oldest first.
+static ATTEMPTS: OnceLock<Mutex<HashMap<String, VecDeque<Instant>>>> = OnceLock::new();
+
+/// Don't let clients that never come back pile up in memory.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Records a generation attempt by `client`. Returns how long the client has
+/// to wait if it already used up its attempts within the window.
+pub fn register_attempt(client: &str, policy: &ClientRateLimit) -> Option<Duration> {
+    if policy.max_attempts == 0 {
+        return None;
+    }
+
+    let mut attempts = ATTEMPTS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    register(&mut attempts, client, policy, Instant::now())
+}
+
+fn register(
+    attempts: &mut HashMap<String, VecDeque<Instant>>,
+    client: &str,
+    policy: &ClientRateLimit,
+    now: Instant,
+) -> Option<Duration> {
+    if attempts.len() >= MAX_TRACKED_CLIENTS {
+        attempts.retain(|_, times| times.back().is_some_and(|last| now.duration_since(*last) < policy.window));
+    }
+
+    let times = attempts.entry(client.to_string()).or_default();
+    while times.front().is_some_and(|first| now.duration_since(*first) >= policy.window) {
+        times.pop_front();
+    }
+
+    if times.len() >= policy.max_attempts as usize {
+        let oldest = times.front().copied().unwrap_or(now);
+        return Some(policy.window.saturating_sub(now.duration_since(oldest)));
+    }
+
+    times.push_back(now);
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: u32) -> ClientRateLimit {
+        ClientRateLimit {
+            max_attempts,
+            window: Duration::from_secs(60),
+            by_user_agent: false,
+            ip_header: None,
+            trusted_proxies: 1,
+        }
+    }
+
+    #[test]
+    fn refuses_attempts_over_the_limit_until_the_window_passed() {
+        let policy = policy(2);
+        let mut attempts = HashMap::new();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(register(&mut attempts, "a", &policy, at(0)), None);
+        assert_eq!(register(&mut attempts, "a", &policy, at(10)), None);
+        assert_eq!(register(&mut attempts, "a", &policy, at(20)), Some(Duration::from_secs(40)));
+        // Other clients have their own attempts
+        assert_eq!(register(&mut attempts, "b", &policy, at(20)), None);
+        // The first attempt dropped out of the window
+        assert_eq!(register(&mut attempts, "a", &policy, at(60)), None);
+        assert_eq!(register(&mut attempts, "a", &policy, at(61)), Some(Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn zero_attempts_disables_the_limit() {
+        for _ in 0..10 {
+            assert_eq!(register_attempt("unlimited", &policy(0)), None);
+        }
+    }
+
+    #[test]
+    fn prunes_clients_without_recent_attempts() {
+        let policy = policy(1);
+        let mut attempts = HashMap::new();
+        let start = Instant::now();
+
+        for client in 0..MAX_TRACKED_CLIENTS - 1 {
+            register(&mut attempts, &client.to_string(), &policy, start);
+        }
+        register(&mut attempts, "recent", &policy, start + Duration::from_secs(30));
+        assert_eq!(attempts.len(), MAX_TRACKED_CLIENTS);
+
+        register(&mut attempts, "new", &policy, start + Duration::from_secs(61));
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts.contains_key("recent"));
+        assert!(attempts.contains_key("new"));
+    }
+}